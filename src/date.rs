@@ -0,0 +1,319 @@
+use crate::entity::{Entity, Statement};
+use serde_json::Value;
+
+const JULIAN: &str = "http://www.wikidata.org/entity/Q1985786";
+
+/// Year, month and day, comparable as a tuple.
+pub type Date = (i64, u8, u8);
+
+/// A time value of Wikidata, in the proleptic Gregorian calendar.
+///
+/// Precision is the one of Wikidata: 9 year, 10 month, 11 day, and below 9 decade (8),
+/// century (7), millennium (6) and so on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WikidataTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub precision: u8,
+}
+
+impl WikidataTime {
+    /// Parse the value of a `time` datavalue, e.g.
+    /// `{"time":"+1830-00-00T00:00:00Z","precision":9,"calendarmodel":"...Q1985727"}`.
+    /// Julian dates with day precision are converted to the Gregorian calendar.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let time = value["time"].as_str()?;
+        let precision = value["precision"].as_u64().unwrap_or(11).min(14) as u8;
+        let (negative, time) = match time.as_bytes().first()? {
+            b'-' => (true, &time[1..]),
+            b'+' => (false, &time[1..]),
+            _ => (false, time),
+        };
+        let mut parts = time.split('T').next()?.splitn(3, '-');
+        let year = parts.next()?.parse::<i64>().ok()?;
+        let month = parts.next()?.parse::<u8>().ok()?;
+        let day = parts.next()?.parse::<u8>().ok()?;
+        let mut time = WikidataTime {
+            year: if negative { -year } else { year },
+            month,
+            day,
+            precision,
+        };
+        if precision >= 11 && month > 0 && day > 0 && value["calendarmodel"] == JULIAN {
+            let (year, month, day) = jdn_to_gregorian(julian_to_jdn(time.year, month, day));
+            time.year = year;
+            time.month = month;
+            time.day = day;
+        }
+        Some(time)
+    }
+
+    pub fn from_statement(statement: &Statement) -> Option<Self> {
+        let datavalue = statement.mainsnak.datavalue.as_ref()?;
+        if datavalue.value_type != "time" {
+            return None;
+        }
+        WikidataTime::from_value(&datavalue.value)
+    }
+
+    /// `1830`, `1830-05` or `1830-05-12` according to the precision. Negative years are BCE.
+    pub fn to_date_string(&self) -> String {
+        let year = if self.year < 0 {
+            format!("-{:04}", -self.year)
+        } else {
+            format!("{:04}", self.year)
+        };
+        match self.precision {
+            0..=9 => year,
+            10 => format!("{}-{:02}", year, self.month.max(1)),
+            _ => format!("{}-{:02}-{:02}", year, self.month.max(1), self.day.max(1)),
+        }
+    }
+
+    /// The first and last day of the period the value stands for, e.g. the whole year 1830
+    /// for year precision.
+    pub fn period(&self) -> (Date, Date) {
+        match self.precision {
+            11..=14 => {
+                let date = (self.year, self.month.max(1), self.day.max(1));
+                (date, date)
+            }
+            10 => {
+                let month = self.month.max(1);
+                (
+                    (self.year, month, 1),
+                    (self.year, month, days_in_month(self.year, month)),
+                )
+            }
+            _ => {
+                let span = 10i64.pow(9 - self.precision.min(9) as u32);
+                let start = self.year.div_euclid(span) * span;
+                ((start, 1, 1), (start + span - 1, 12, 31))
+            }
+        }
+    }
+}
+
+/// The number of days of `month` in the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: u8) -> u8 {
+    let leap = year.rem_euclid(4) == 0 && (year.rem_euclid(100) != 0 || year.rem_euclid(400) == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn julian_to_jdn(year: i64, month: u8, day: u8) -> i64 {
+    let a = (14 - month as i64) / 12;
+    let y = year + 4800 - a;
+    let m = month as i64 + 12 * a - 3;
+    day as i64 + (153 * m + 2) / 5 + 365 * y + y.div_euclid(4) - 32083
+}
+
+fn jdn_to_gregorian(jdn: i64) -> Date {
+    let a = jdn + 32044;
+    let b = (4 * a + 3).div_euclid(146097);
+    let c = a - (146097 * b).div_euclid(4);
+    let d = (4 * c + 3).div_euclid(1461);
+    let e = c - (1461 * d).div_euclid(4);
+    let m = (5 * e + 2) / 153;
+    let day = e - (153 * m + 2) / 5 + 1;
+    let month = m + 3 - 12 * (m / 10);
+    let year = 100 * b + d - 4800 + m / 10;
+    (year, month as u8, day as u8)
+}
+
+/// Keeps the entities with a value of `property` in a range of dates, e.g. `P569=1900..1950`.
+/// A value matches when its whole period is in the range, so `1830` with year precision is in
+/// `1800..1900` but not in `1830-06..1900`.
+#[derive(Debug, Clone)]
+pub struct TimeRange {
+    property: String,
+    start: Option<Date>,
+    end: Option<Date>,
+}
+
+/// `1900`, `1900-05` or `1900-05-12`. The first or the last day of it.
+fn parse_bound(bound: &str, last: bool) -> Result<Option<Date>, String> {
+    let bound = bound.trim();
+    if bound.is_empty() {
+        return Ok(None);
+    }
+    let (negative, bound) = match bound.strip_prefix('-') {
+        Some(bound) => (true, bound),
+        None => (false, bound),
+    };
+    let invalid = |_| format!("invalid date: {}", bound);
+    let mut parts = bound.splitn(3, '-');
+    let year = parts.next().unwrap().parse::<i64>().map_err(invalid)?;
+    let year = if negative { -year } else { year };
+    let month = match parts.next() {
+        Some(month) => month.parse::<u8>().map_err(invalid)?,
+        None => return Ok(Some(if last { (year, 12, 31) } else { (year, 1, 1) })),
+    };
+    let day = match parts.next() {
+        Some(day) => day.parse::<u8>().map_err(invalid)?,
+        None => {
+            return Ok(Some(if last {
+                (year, month, days_in_month(year, month))
+            } else {
+                (year, month, 1)
+            }))
+        }
+    };
+    Ok(Some((year, month, day)))
+}
+
+impl TimeRange {
+    /// `PROPERTY=START..END`, both ends inclusive and optional, e.g. `P569=1900..1950`,
+    /// `P571=..1850-06` or `P577=2020-01-01..`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (property, range) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("{}: PROPERTY=START..END expected", spec))?;
+        let (start, end) = range
+            .split_once("..")
+            .ok_or_else(|| format!("{}: START..END expected", spec))?;
+        Ok(TimeRange {
+            property: property.trim().to_uppercase(),
+            start: parse_bound(start, false)?,
+            end: parse_bound(end, true)?,
+        })
+    }
+
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    pub fn contains(&self, time: &WikidataTime) -> bool {
+        let (first, last) = time.period();
+        self.start.is_none_or(|start| start <= first) && self.end.is_none_or(|end| last <= end)
+    }
+
+    /// True if any value of the property is in the range.
+    pub fn matches(&self, entity: &Entity) -> bool {
+        match entity.claims.get(self.property.as_str()) {
+            Some(statements) => statements
+                .iter()
+                .filter_map(WikidataTime::from_statement)
+                .any(|time| self.contains(&time)),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::date::*;
+    use serde_json::json;
+
+    fn time(time: &str, precision: u8, calendar: &str) -> WikidataTime {
+        WikidataTime::from_value(&json!({
+            "time": time,
+            "precision": precision,
+            "calendarmodel": format!("http://www.wikidata.org/entity/{}", calendar)
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn check_date_string() {
+        assert_eq!(
+            time("+1830-00-00T00:00:00Z", 9, "Q1985727").to_date_string(),
+            "1830"
+        );
+        assert_eq!(
+            time("+1830-05-00T00:00:00Z", 10, "Q1985727").to_date_string(),
+            "1830-05"
+        );
+        assert_eq!(
+            time("+2013-10-28T00:00:00Z", 11, "Q1985727").to_date_string(),
+            "2013-10-28"
+        );
+        assert_eq!(
+            time("-0500-00-00T00:00:00Z", 9, "Q1985786").to_date_string(),
+            "-0500"
+        );
+    }
+
+    #[test]
+    fn check_julian() {
+        // Shakespeare died on 23 April 1616 (Julian), 3 May 1616 (Gregorian)
+        assert_eq!(
+            time("+1616-04-23T00:00:00Z", 11, "Q1985786").to_date_string(),
+            "1616-05-03"
+        );
+        // the last Julian day before the Gregorian reform
+        assert_eq!(
+            time("+1582-10-04T00:00:00Z", 11, "Q1985786").to_date_string(),
+            "1582-10-14"
+        );
+        // not converted without day precision
+        assert_eq!(
+            time("+1616-04-00T00:00:00Z", 10, "Q1985786").to_date_string(),
+            "1616-04"
+        );
+    }
+
+    #[test]
+    fn check_month_period() {
+        assert_eq!(
+            time("+1830-06-00T00:00:00Z", 10, "Q1985727").period(),
+            ((1830, 6, 1), (1830, 6, 30))
+        );
+        assert_eq!(
+            time("+1830-02-00T00:00:00Z", 10, "Q1985727").period(),
+            ((1830, 2, 1), (1830, 2, 28))
+        );
+        // leap years, 1900 is not one
+        assert_eq!(
+            time("+2024-02-00T00:00:00Z", 10, "Q1985727").period(),
+            ((2024, 2, 1), (2024, 2, 29))
+        );
+        assert_eq!(
+            time("+2000-02-00T00:00:00Z", 10, "Q1985727").period(),
+            ((2000, 2, 1), (2000, 2, 29))
+        );
+        assert_eq!(
+            time("+1900-02-00T00:00:00Z", 10, "Q1985727").period(),
+            ((1900, 2, 1), (1900, 2, 28))
+        );
+        assert_eq!(
+            time("+1830-12-00T00:00:00Z", 10, "Q1985727").period(),
+            ((1830, 12, 1), (1830, 12, 31))
+        );
+        // a June value is in a range ending on June 30
+        let range = TimeRange::parse("P569=1830-06-01..1830-06-30").unwrap();
+        assert!(range.contains(&time("+1830-06-00T00:00:00Z", 10, "Q1985727")));
+    }
+
+    #[test]
+    fn check_time_range() {
+        let range = TimeRange::parse("p569=1900..1950").unwrap();
+        assert_eq!(range.property(), "P569");
+        assert!(range.contains(&time("+1900-01-01T00:00:00Z", 11, "Q1985727")));
+        assert!(range.contains(&time("+1950-00-00T00:00:00Z", 9, "Q1985727")));
+        assert!(!range.contains(&time("+1951-01-01T00:00:00Z", 11, "Q1985727")));
+        // the 1940s
+        assert!(range.contains(&time("+1940-00-00T00:00:00Z", 8, "Q1985727")));
+        // the 20th century is wider than the range
+        assert!(!range.contains(&time("+1900-00-00T00:00:00Z", 7, "Q1985727")));
+
+        let range = TimeRange::parse("P571=..1850-06").unwrap();
+        assert!(range.contains(&time("+1830-00-00T00:00:00Z", 9, "Q1985727")));
+        assert!(!range.contains(&time("+1850-07-01T00:00:00Z", 11, "Q1985727")));
+        assert!(TimeRange::parse("P571=1850").is_err());
+
+        // the last day of a 30 day month
+        let range = TimeRange::parse("P569=..1850-06").unwrap();
+        assert_eq!(range.end, Some((1850, 6, 30)));
+        let range = TimeRange::parse("P569=..1900-02").unwrap();
+        assert_eq!(range.end, Some((1900, 2, 28)));
+        let range = TimeRange::parse("P569=..2000-02").unwrap();
+        assert_eq!(range.end, Some((2000, 2, 29)));
+        assert!(TimeRange::parse("P571=18x0..").is_err());
+    }
+}
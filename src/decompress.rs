@@ -0,0 +1,489 @@
+use bzip2::read::MultiBzDecoder;
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use futures::executor::{block_on, ThreadPool};
+use futures::future::RemoteHandle;
+use futures::task::SpawnExt;
+use log::info;
+use regex::bytes::Regex;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Cursor, Read};
+
+/// Compressed bytes handed to one decompression task.
+const BATCH_SIZE: usize = 4 * 1024 * 1024;
+/// How far a file is scanned for a second stream before falling back to a single thread.
+const PROBE_SIZE: usize = 64 * 1024 * 1024;
+/// Compressed bytes after a gzip header candidate that are decompressed to confirm it.
+const GZ_CHECK_SIZE: usize = 1024 * 1024;
+/// Decompressed bytes that confirm a gzip header candidate, unless the member ends before.
+const GZ_CHECK_OUTPUT: usize = 64 * 1024;
+
+/// Decompress a dump according to its format.
+///
+/// * bgzip (block-indexed gzip), multi-member gzip (pigz with `--independent`, concatenated
+///   gzip files) and multistream bzip2 (pbzip2, lbzip2 with `-n`) are decompressed in parallel
+///   on a pool of `threads` decoding threads, keeping the order of the data.
+/// * single member gzip and single stream bzip2 files are decompressed on the reader thread.
+/// * anything else is read as uncompressed JSON.
+pub fn open_input<R: Read + Send + 'static>(input: R, threads: usize) -> Box<dyn Read + Send> {
+    let mut reader = BufReader::new(input);
+    let magic = reader.fill_buf().expect("Input file read error").to_vec();
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        if is_bgzf(&magic) {
+            info!("bgzip input, decompress in parallel...");
+            return Box::new(ParallelReader::new(
+                BgzfBatches::new(reader, BATCH_SIZE),
+                decode_gz,
+                threads,
+            ));
+        }
+        let probe = read_probe(&mut reader);
+        let multimember = is_multimember_gz(&probe);
+        let rest = Cursor::new(probe).chain(reader);
+        if multimember {
+            info!("multi-member gzip input, decompress in parallel...");
+            Box::new(ParallelReader::new(
+                StreamBatches::gzip(rest, BATCH_SIZE),
+                decode_gz,
+                threads,
+            ))
+        } else {
+            info!("gzip input, decompress on the reader thread...");
+            Box::new(MultiGzDecoder::new(rest))
+        }
+    } else if magic.starts_with(b"BZh") {
+        let probe = read_probe(&mut reader);
+        let multistream = is_multistream_bz(&probe);
+        let rest = Cursor::new(probe).chain(reader);
+        if multistream {
+            info!("multistream bzip2 input, decompress in parallel...");
+            Box::new(ParallelReader::new(
+                StreamBatches::bzip2(rest, BATCH_SIZE),
+                decode_bz,
+                threads,
+            ))
+        } else {
+            info!("bzip2 input, decompress on the reader thread...");
+            Box::new(MultiBzDecoder::new(rest))
+        }
+    } else {
+        Box::new(reader)
+    }
+}
+
+/// A gzip member with the `BC` extra subfield of the BGZF format.
+fn is_bgzf(header: &[u8]) -> bool {
+    header.len() >= 18
+        && header[3] & 0x04 != 0
+        && header[12] == b'B'
+        && header[13] == b'C'
+        && header[14] == 2
+}
+
+/// The head of the file, to look for a second stream in it.
+fn read_probe<R: Read>(reader: &mut R) -> Vec<u8> {
+    let mut probe = vec![];
+    reader
+        .take(PROBE_SIZE as u64)
+        .read_to_end(&mut probe)
+        .expect("Input file read error");
+    probe
+}
+
+fn gz_member_header() -> Regex {
+    // magic + deflate + no reserved flags + mtime + extra flags + operating system
+    Regex::new(r"(?s-u)\x1f\x8b\x08[\x00-\x1f].{4}[\x00\x02\x04][\x00-\x0d\xff]").unwrap()
+}
+
+/// A gzip member header at the start of `data`, confirmed by decompressing the start of the
+/// member, since the header bytes can also occur inside compressed data.
+fn is_gz_member(data: &[u8]) -> bool {
+    let check = &data[..data.len().min(GZ_CHECK_SIZE)];
+    let mut decoder = GzDecoder::new(check);
+    let mut output = vec![0u8; GZ_CHECK_OUTPUT];
+    let mut total = 0;
+    while total < output.len() {
+        match decoder.read(&mut output[total..]) {
+            // the member ended and its checksum matched
+            Ok(0) => return true,
+            Ok(read) => total += read,
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+fn is_multimember_gz(head: &[u8]) -> bool {
+    gz_member_header()
+        .find_iter(head)
+        .any(|member| member.start() > 0 && is_gz_member(&head[member.start()..]))
+}
+
+fn bz_stream_header() -> Regex {
+    // "BZh" + block size + block magic (pi)
+    Regex::new(r"(?-u)BZh[1-9]1AY&SY").unwrap()
+}
+
+fn is_multistream_bz(head: &[u8]) -> bool {
+    bz_stream_header()
+        .find_iter(head)
+        .any(|stream| stream.start() > 0)
+}
+
+fn decode_gz(batch: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(batch.len() * 4);
+    MultiGzDecoder::new(batch.as_slice()).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+fn decode_bz(batch: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(batch.len() * 4);
+    MultiBzDecoder::new(batch.as_slice()).read_to_end(&mut decoded)?;
+    Ok(decoded)
+}
+
+/// Splits a bgzip file into batches of whole BGZF blocks, using the block size in each header.
+struct BgzfBatches<R: Read> {
+    reader: R,
+    batch_size: usize,
+}
+
+impl<R: Read> BgzfBatches<R> {
+    fn new(reader: R, batch_size: usize) -> Self {
+        BgzfBatches { reader, batch_size }
+    }
+
+    /// Append one block to `batch`. Returns false at the end of the file.
+    fn read_block(&mut self, batch: &mut Vec<u8>) -> io::Result<bool> {
+        let mut header = [0u8; 18];
+        let read = read_full(&mut self.reader, &mut header)?;
+        if read == 0 {
+            return Ok(false);
+        }
+        if read < header.len() || !is_bgzf(&header) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "broken BGZF block header",
+            ));
+        }
+        let block_size = u16::from_le_bytes([header[16], header[17]]) as usize + 1;
+        let start = batch.len();
+        batch.extend_from_slice(&header);
+        batch.resize(start + block_size, 0);
+        self.reader.read_exact(&mut batch[start + header.len()..])?;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Iterator for BgzfBatches<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        let mut batch = vec![];
+        while batch.len() < self.batch_size {
+            match self.read_block(&mut batch) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        if batch.is_empty() {
+            None
+        } else {
+            Some(Ok(batch))
+        }
+    }
+}
+
+/// Splits a file of concatenated streams, multistream bzip2 or multi-member gzip, into batches
+/// of whole streams.
+///
+/// Streams start byte aligned with a header, so a batch ends right before the first header after
+/// `batch_size` bytes. A gzip header is short enough to occur inside compressed data, so a gzip
+/// candidate is only taken when the start of its member decompresses. A false bzip2 header is
+/// possible in theory, it fails the decompression instead of producing wrong output.
+struct StreamBatches<R: Read> {
+    reader: R,
+    batch_size: usize,
+    buffer: Vec<u8>,
+    header: Regex,
+    /// Confirms a header candidate, given the buffer from the candidate on.
+    is_stream: fn(&[u8]) -> bool,
+    /// Bytes after a candidate that `is_stream` needs, unless the file ends before.
+    check_size: usize,
+    eof: bool,
+}
+
+impl<R: Read> StreamBatches<R> {
+    fn bzip2(reader: R, batch_size: usize) -> Self {
+        StreamBatches::new(reader, batch_size, bz_stream_header(), |_| true, 0)
+    }
+
+    fn gzip(reader: R, batch_size: usize) -> Self {
+        StreamBatches::new(
+            reader,
+            batch_size,
+            gz_member_header(),
+            is_gz_member,
+            GZ_CHECK_SIZE,
+        )
+    }
+
+    fn new(
+        reader: R,
+        batch_size: usize,
+        header: Regex,
+        is_stream: fn(&[u8]) -> bool,
+        check_size: usize,
+    ) -> Self {
+        StreamBatches {
+            reader,
+            batch_size,
+            buffer: vec![],
+            header,
+            is_stream,
+            check_size,
+            eof: false,
+        }
+    }
+
+    /// The start of the first stream after `batch_size` bytes. `Err` when a candidate needs more
+    /// data to be checked.
+    fn split_position(&self) -> Result<Option<usize>, ()> {
+        let from = self.batch_size.min(self.buffer.len());
+        for stream in self.header.find_iter(&self.buffer[from..]) {
+            let start = from + stream.start();
+            if start == 0 {
+                continue;
+            }
+            if !self.eof && start + self.check_size > self.buffer.len() {
+                return Err(());
+            }
+            if (self.is_stream)(&self.buffer[start..]) {
+                return Ok(Some(start));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for StreamBatches<R> {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<u8>>> {
+        loop {
+            if let Ok(Some(position)) = self.split_position() {
+                let rest = self.buffer.split_off(position);
+                return Some(Ok(std::mem::replace(&mut self.buffer, rest)));
+            }
+            if self.eof {
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                return Some(Ok(std::mem::take(&mut self.buffer)));
+            }
+            let len = self.buffer.len();
+            self.buffer.resize(len + 1024 * 1024, 0);
+            match read_full(&mut self.reader, &mut self.buffer[len..]) {
+                Ok(read) => {
+                    self.buffer.truncate(len + read);
+                    self.eof = read == 0;
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Like `read_exact`, but returns the number of bytes read at the end of the file.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(read) => total += read,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+/// Decompresses batches on its own thread pool and reads the results in the original order.
+/// The pool is not shared with the parser, so decoding never waits behind parse chunks.
+struct ParallelReader<I: Iterator<Item = io::Result<Vec<u8>>>> {
+    batches: I,
+    decode: fn(Vec<u8>) -> io::Result<Vec<u8>>,
+    pool: ThreadPool,
+    pending: VecDeque<RemoteHandle<io::Result<Vec<u8>>>>,
+    max_pending: usize,
+    current: Cursor<Vec<u8>>,
+}
+
+impl<I: Iterator<Item = io::Result<Vec<u8>>>> ParallelReader<I> {
+    fn new(batches: I, decode: fn(Vec<u8>) -> io::Result<Vec<u8>>, threads: usize) -> Self {
+        ParallelReader {
+            batches,
+            decode,
+            pool: ThreadPool::builder()
+                .pool_size(threads)
+                .name_prefix("decode-")
+                .create()
+                .expect("Create thread pool error"),
+            pending: VecDeque::new(),
+            // enough batches in flight to keep every worker busy
+            max_pending: 2 * threads,
+            current: Cursor::new(vec![]),
+        }
+    }
+
+    fn spawn_batches(&mut self) -> io::Result<()> {
+        while self.pending.len() < self.max_pending {
+            match self.batches.next() {
+                Some(batch) => {
+                    let batch = batch?;
+                    let decode = self.decode;
+                    let handle = self
+                        .pool
+                        .spawn_with_handle(async move { decode(batch) })
+                        .expect("Spawn error...");
+                    self.pending.push_back(handle);
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<I: Iterator<Item = io::Result<Vec<u8>>>> Read for ParallelReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            self.spawn_batches()?;
+            match self.pending.pop_front() {
+                Some(handle) => self.current = Cursor::new(block_on(handle)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decompress::*;
+    use bzip2::write::BzEncoder;
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+    use std::io::Write;
+
+    fn lines(from: usize, to: usize) -> String {
+        (from..to)
+            .map(|i| format!("{{\"id\":\"Q{}\"}},\n", i))
+            .collect()
+    }
+
+    fn bgzf_block(data: &str) -> Vec<u8> {
+        let mut encoder = GzBuilder::new()
+            .extra(vec![b'B', b'C', 2, 0, 0, 0])
+            .write(Vec::new(), Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        let mut block = encoder.finish().unwrap();
+        let block_size = (block.len() - 1) as u16;
+        block[16..18].copy_from_slice(&block_size.to_le_bytes());
+        block
+    }
+
+    fn gz_member(data: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn bz_stream(data: &str) -> Vec<u8> {
+        let mut encoder = BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        encoder.write_all(data.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn check_parallel_bgzf() {
+        let mut compressed = vec![];
+        let mut expected = String::new();
+        for i in 0..20 {
+            let data = lines(i * 100, (i + 1) * 100);
+            compressed.extend(bgzf_block(&data));
+            expected.push_str(&data);
+        }
+        assert!(is_bgzf(&compressed));
+        let batches = BgzfBatches::new(compressed.as_slice(), 1000);
+        let mut reader = ParallelReader::new(batches, decode_gz, 4);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn check_parallel_bz() {
+        let mut compressed = vec![];
+        let mut expected = String::new();
+        for i in 0..20 {
+            let data = lines(i * 100, (i + 1) * 100);
+            compressed.extend(bz_stream(&data));
+            expected.push_str(&data);
+        }
+        assert!(is_multistream_bz(&compressed));
+        let batches = StreamBatches::bzip2(compressed.as_slice(), 1000);
+        assert!(batches.count() > 1);
+        let batches = StreamBatches::bzip2(compressed.as_slice(), 1000);
+        let mut reader = ParallelReader::new(batches, decode_bz, 4);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn check_parallel_multimember_gz() {
+        let mut compressed = vec![];
+        let mut expected = String::new();
+        for i in 0..20 {
+            let data = lines(i * 100, (i + 1) * 100);
+            compressed.extend(gz_member(&data));
+            expected.push_str(&data);
+        }
+        assert!(!is_bgzf(&compressed));
+        assert!(is_multimember_gz(&compressed));
+        let batches = StreamBatches::gzip(compressed.as_slice(), 1000);
+        assert!(batches.count() > 1);
+        let batches = StreamBatches::gzip(compressed.as_slice(), 1000);
+        let mut reader = ParallelReader::new(batches, decode_gz, 4);
+        let mut decoded = String::new();
+        reader.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn check_false_gz_header() {
+        // a member header inside the data of a stored member is not a member start
+        let mut fake = vec![];
+        fake.extend(gz_member("x"));
+        fake.truncate(10);
+        fake.extend(b"garbage, not deflate data");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&fake).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(gz_member_header().is_match(&compressed[1..]));
+        assert!(!is_multimember_gz(&compressed));
+        assert_eq!(StreamBatches::gzip(compressed.as_slice(), 1).count(), 1);
+    }
+
+    #[test]
+    fn check_format_detection() {
+        let data = lines(0, 10);
+        assert!(!is_bgzf(&gz_member(&data)));
+        assert!(!is_multimember_gz(&gz_member(&data)));
+        assert!(!is_multistream_bz(&bz_stream(&data)));
+    }
+}
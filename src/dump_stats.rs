@@ -0,0 +1,318 @@
+use crate::entity::Entity;
+use crate::parser::{scan_entities, Config};
+use crate::stats::StatsReport;
+use log::info;
+use serde_derive::Serialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PropertyUsage {
+    /// Entities with at least one statement of the property.
+    pub entities: u64,
+    pub statements: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LanguageCoverage {
+    pub labels: u64,
+    pub descriptions: u64,
+    /// Entities with at least one alias.
+    pub aliases: u64,
+}
+
+/// Counts of a whole dump, to choose the properties and the language of a run.
+#[derive(Debug, Default)]
+pub struct DumpStats {
+    pub entities: u64,
+    /// Entity type, e.g. `item`, to the number of entities.
+    pub entity_types: HashMap<String, u64>,
+    /// Property id to its usage.
+    pub properties: HashMap<String, PropertyUsage>,
+    /// Language code to its coverage.
+    pub languages: HashMap<String, LanguageCoverage>,
+    /// Class id to the number of entities that are an instance of it (P31).
+    pub classes: HashMap<String, u64>,
+}
+
+impl DumpStats {
+    pub fn record(&mut self, entity: &Entity) {
+        self.entities += 1;
+        *self
+            .entity_types
+            .entry(entity.entity_type.to_string())
+            .or_insert(0) += 1;
+        for (property, statements) in &entity.claims {
+            let usage = self.properties.entry(property.to_string()).or_default();
+            usage.entities += 1;
+            usage.statements += statements.len() as u64;
+        }
+        for lang in entity.labels.keys() {
+            self.languages.entry(lang.to_string()).or_default().labels += 1;
+        }
+        for lang in entity.descriptions.keys() {
+            self.languages
+                .entry(lang.to_string())
+                .or_default()
+                .descriptions += 1;
+        }
+        for (lang, aliases) in &entity.aliases {
+            if !aliases.is_empty() {
+                self.languages.entry(lang.to_string()).or_default().aliases += 1;
+            }
+        }
+        let mut classes: Vec<&str> = entity
+            .claims
+            .get("P31")
+            .into_iter()
+            .flatten()
+            .filter_map(|statement| statement.value_id())
+            .collect();
+        classes.sort();
+        classes.dedup();
+        for class in classes {
+            *self.classes.entry(class.to_string()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: DumpStats) {
+        self.entities += other.entities;
+        for (entity_type, count) in other.entity_types {
+            *self.entity_types.entry(entity_type).or_insert(0) += count;
+        }
+        for (property, usage) in other.properties {
+            let total = self.properties.entry(property).or_default();
+            total.entities += usage.entities;
+            total.statements += usage.statements;
+        }
+        for (lang, coverage) in other.languages {
+            let total = self.languages.entry(lang).or_default();
+            total.labels += coverage.labels;
+            total.descriptions += coverage.descriptions;
+            total.aliases += coverage.aliases;
+        }
+        for (class, count) in other.classes {
+            *self.classes.entry(class).or_insert(0) += count;
+        }
+    }
+
+    /// The report with the `top` most frequent P31 classes and the counters of the `run`.
+    pub fn report(&self, run: &StatsReport, top: usize) -> DumpStatsReport {
+        DumpStatsReport {
+            lines_read: run.lines_read,
+            entities: self.entities,
+            errors: run.errors,
+            entity_types: self.entity_types.clone().into_iter().collect(),
+            properties: self.properties.clone().into_iter().collect(),
+            languages: self.languages.clone().into_iter().collect(),
+            top_classes: top_counts(&self.classes, top),
+        }
+    }
+}
+
+/// The `top` largest counts, ties by id.
+fn top_counts(counts: &HashMap<String, u64>, top: usize) -> Vec<(String, u64)> {
+    let mut counts: Vec<(String, u64)> = counts
+        .iter()
+        .map(|(id, count)| (id.clone(), *count))
+        .collect();
+    counts.sort_by(|a, b| (Reverse(a.1), &a.0).cmp(&(Reverse(b.1), &b.0)));
+    counts.truncate(top);
+    counts
+}
+
+/// Output of the `stats` subcommand.
+#[derive(Debug, Clone, Serialize)]
+pub struct DumpStatsReport {
+    pub lines_read: u64,
+    pub entities: u64,
+    pub errors: u64,
+    pub entity_types: BTreeMap<String, u64>,
+    pub properties: BTreeMap<String, PropertyUsage>,
+    pub languages: BTreeMap<String, LanguageCoverage>,
+    /// `[class id, entities]`, the most frequent first.
+    pub top_classes: Vec<(String, u64)>,
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+impl DumpStatsReport {
+    pub fn to_json_string(&self) -> String {
+        serde_json::to_string_pretty(self).expect("to_json_string Error...")
+    }
+
+    /// Plain text tables, with the `top` most used properties and languages.
+    pub fn to_table(&self, top: usize) -> String {
+        let total = self.entities;
+        let mut text = String::new();
+        let _ = writeln!(text, "lines read: {}", self.lines_read);
+        let _ = writeln!(text, "entities:   {}", total);
+        let _ = writeln!(text, "errors:     {}", self.errors);
+
+        let _ = writeln!(text, "\n{:<12} {:>12} {:>8}", "type", "entities", "%");
+        for (entity_type, count) in &self.entity_types {
+            let _ = writeln!(
+                text,
+                "{:<12} {:>12} {:>8.2}",
+                entity_type,
+                count,
+                percent(*count, total)
+            );
+        }
+
+        let mut properties: Vec<(&String, &PropertyUsage)> = self.properties.iter().collect();
+        properties.sort_by_key(|(id, usage)| (Reverse(usage.entities), *id));
+        let _ = writeln!(
+            text,
+            "\n{:<12} {:>12} {:>8} {:>12}",
+            "property", "entities", "%", "statements"
+        );
+        for (property, usage) in properties.into_iter().take(top) {
+            let _ = writeln!(
+                text,
+                "{:<12} {:>12} {:>8.2} {:>12}",
+                property,
+                usage.entities,
+                percent(usage.entities, total),
+                usage.statements
+            );
+        }
+
+        let mut languages: Vec<(&String, &LanguageCoverage)> = self.languages.iter().collect();
+        languages.sort_by_key(|(lang, coverage)| (Reverse(coverage.labels), *lang));
+        let _ = writeln!(
+            text,
+            "\n{:<12} {:>12} {:>8} {:>12} {:>8} {:>12}",
+            "language", "labels", "%", "descriptions", "%", "aliases"
+        );
+        for (lang, coverage) in languages.into_iter().take(top) {
+            let _ = writeln!(
+                text,
+                "{:<12} {:>12} {:>8.2} {:>12} {:>8.2} {:>12}",
+                lang,
+                coverage.labels,
+                percent(coverage.labels, total),
+                coverage.descriptions,
+                percent(coverage.descriptions, total),
+                coverage.aliases
+            );
+        }
+
+        let _ = writeln!(text, "\n{:<12} {:>12} {:>8}", "P31 class", "entities", "%");
+        for (class, count) in &self.top_classes {
+            let _ = writeln!(
+                text,
+                "{:<12} {:>12} {:>8.2}",
+                class,
+                count,
+                percent(*count, total)
+            );
+        }
+        text
+    }
+}
+
+/// Parse every entity of the dump with all languages and properties and count them.
+pub fn scan_dump(config: &Config, top: usize) -> DumpStatsReport {
+    let (stats, run) = scan_entities(
+        config,
+        None,
+        None,
+        |_| true,
+        DumpStats::default,
+        |entity, stats| stats.record(entity),
+        DumpStats::merge,
+    );
+    info!(
+        "{} entities, {} properties, {} languages",
+        stats.entities,
+        stats.properties.len(),
+        stats.languages.len()
+    );
+    stats.report(&run, top)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dump_stats::*;
+    use crate::entity::EntitySeed;
+    use crate::test_util::temp_path;
+
+    const TOKYO: &str = r#"{"type":"item","id":"Q1490","labels":{"ja":{"language":"ja","value":"東京都"},"en":{"language":"en","value":"Tokyo"}},"descriptions":{"en":{"language":"en","value":"capital of Japan"}},"aliases":{"ja":[{"language":"ja","value":"東京"}]},"claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":1200957,"id":"Q1200957"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"},{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":515,"id":"Q515"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"}],"P17":[{"mainsnak":{"snaktype":"value","property":"P17","datavalue":{"value":{"entity-type":"item","numeric-id":17,"id":"Q17"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"}]},"sitelinks":{}}"#;
+    const OSAKA: &str = r#"{"type":"item","id":"Q35765","labels":{"ja":{"language":"ja","value":"大阪市"}},"descriptions":[],"aliases":{},"claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":515,"id":"Q515"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"},{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":515,"id":"Q515"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"deprecated"}]},"sitelinks":{}}"#;
+    const INSTANCE_OF: &str = r#"{"type":"property","datatype":"wikibase-item","id":"P31","labels":{"en":{"language":"en","value":"instance of"}},"descriptions":{},"aliases":{},"claims":{}}"#;
+
+    fn stats_of(lines: &[&str]) -> DumpStats {
+        let mut stats = DumpStats::default();
+        for line in lines {
+            let entity = Entity::parse(line.as_bytes(), EntitySeed::all()).unwrap();
+            stats.record(&entity);
+        }
+        stats
+    }
+
+    #[test]
+    fn check_record() {
+        let stats = stats_of(&[TOKYO, OSAKA, INSTANCE_OF]);
+        assert_eq!(stats.entities, 3);
+        assert_eq!(stats.entity_types["item"], 2);
+        assert_eq!(stats.entity_types["property"], 1);
+        let p31 = PropertyUsage {
+            entities: 2,
+            statements: 4,
+        };
+        assert_eq!(stats.properties["P31"], p31);
+        let ja = LanguageCoverage {
+            labels: 2,
+            descriptions: 0,
+            aliases: 1,
+        };
+        assert_eq!(stats.languages["ja"], ja);
+        assert_eq!(stats.languages["en"].labels, 2);
+        // counted once per entity
+        assert_eq!(stats.classes["Q515"], 2);
+    }
+
+    #[test]
+    fn check_merge_and_report() {
+        let mut stats = stats_of(&[TOKYO]);
+        stats.merge(stats_of(&[OSAKA, INSTANCE_OF]));
+        let report = stats.report(&StatsReport::default(), 1);
+        assert_eq!(report.entities, 3);
+        assert_eq!(report.properties["P31"].statements, 4);
+        assert_eq!(report.top_classes, vec![(String::from("Q515"), 2)]);
+        let table = report.to_table(10);
+        assert!(table.contains("entities:   3"));
+        assert!(table
+            .lines()
+            .any(|line| line.starts_with("P31") && line.contains("66.67")));
+        let json: serde_json::Value = serde_json::from_str(&report.to_json_string()).unwrap();
+        assert_eq!(json["languages"]["ja"]["labels"], 2);
+        assert_eq!(json["top_classes"][0][0], "Q515");
+    }
+
+    #[test]
+    fn check_scan_dump() {
+        let path = temp_path("check_scan_dump.json");
+        let json = format!("[\n{},\n{{\"type\":\"item\",\n{}\n]\n", TOKYO, OSAKA);
+        std::fs::write(&path, json).unwrap();
+
+        let config = Config::builder()
+            .input_file(path.to_str().unwrap())
+            .chunk_size(2)
+            .build();
+        let report = scan_dump(&config, 1);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.lines_read, 5);
+        assert_eq!(report.entities, 2);
+        assert_eq!(report.errors, 1);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::entity::Entity;
+use crate::idset::encode;
+use crate::parser::{scan_entities, Config};
+use crate::prefilter::Prefilter;
+use log::info;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+/// Size of a record of the binary format.
+pub const RECORD_BYTES: usize = 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeFormat {
+    /// Tab separated `source property target rank` lines, e.g. `Q1490 P31 Q515 normal`.
+    Tsv,
+    /// Little-endian records of `RECORD_BYTES`: source `u64`, target `u64`, property `u32`,
+    /// rank `u8` (0 deprecated, 1 normal, 2 preferred) and 3 zero bytes. Ids are encoded as
+    /// the type letter in the top byte and the number below, e.g. `Q42` is `0x51 << 56 | 42`.
+    Binary,
+}
+
+impl EdgeFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format {
+            "tsv" => Ok(EdgeFormat::Tsv),
+            "binary" => Ok(EdgeFormat::Binary),
+            _ => Err(format!("{}: tsv or binary expected", format)),
+        }
+    }
+}
+
+fn rank_code(rank: &str) -> u8 {
+    match rank {
+        "deprecated" => 0,
+        "preferred" => 2,
+        _ => 1,
+    }
+}
+
+/// Append the edges of `entity` to `out`: one per statement whose value is an entity id, of
+/// `properties` or of every property if `None`. Returns the number of edges.
+pub fn write_edges(
+    entity: &Entity,
+    properties: Option<&[String]>,
+    format: EdgeFormat,
+    out: &mut Vec<u8>,
+) -> u64 {
+    let mut count = 0;
+    for (property, statements) in &entity.claims {
+        if let Some(properties) = properties {
+            if !properties.iter().any(|x| x == property) {
+                continue;
+            }
+        }
+        for statement in statements {
+            let target = match statement.value_id() {
+                Some(target) => target,
+                None => continue,
+            };
+            let rank = if statement.rank.is_empty() {
+                "normal"
+            } else {
+                statement.rank.as_ref()
+            };
+            match format {
+                EdgeFormat::Tsv => {
+                    let _ = writeln!(out, "{}\t{}\t{}\t{}", entity.id, property, target, rank);
+                }
+                EdgeFormat::Binary => {
+                    // lexeme forms and senses, e.g. L7-F1, have no numeric id
+                    let (source, target) = match (encode(&entity.id), encode(target)) {
+                        (Some(source), Some(target)) => (source, target),
+                        _ => continue,
+                    };
+                    let property = match property[1..].parse::<u32>() {
+                        Ok(property) => property,
+                        Err(_) => continue,
+                    };
+                    out.extend_from_slice(&source.to_le_bytes());
+                    out.extend_from_slice(&target.to_le_bytes());
+                    out.extend_from_slice(&property.to_le_bytes());
+                    out.extend_from_slice(&[rank_code(rank), 0, 0, 0]);
+                }
+            }
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Write the edge list of the whole dump to `path`. Chunks are written in the order they finish,
+/// so the edges are grouped by source but not sorted. With `properties`, the lines without any
+/// of them are skipped before parsing. Returns the number of edges.
+pub fn scan_edges(
+    config: &Config,
+    properties: Option<Vec<String>>,
+    format: EdgeFormat,
+    path: &str,
+) -> u64 {
+    let file = File::create(path).unwrap_or_else(|_| panic!("can't create edge file[{}]", path));
+    let writer = Arc::new(Mutex::new(BufWriter::new(file)));
+    let shared = writer.clone();
+    let selected = properties.clone();
+    let prefilter = properties.as_ref().map(|properties| {
+        let patterns: Vec<String> = properties
+            .iter()
+            .map(|property| Prefilter::property_pattern(property))
+            .collect();
+        Prefilter::new(&patterns)
+    });
+    let ((_, edges), _) = scan_entities(
+        config,
+        Some(vec![]),
+        properties,
+        |article| {
+            prefilter
+                .as_ref()
+                .is_none_or(|prefilter| prefilter.is_any_match(article))
+        },
+        <(Vec<u8>, u64)>::default,
+        move |entity, (out, count)| {
+            *count += write_edges(entity, selected.as_deref(), format, out);
+        },
+        move |(_, edges), (out, count)| {
+            shared
+                .lock()
+                .unwrap()
+                .write_all(&out)
+                .expect("can't write edges");
+            *edges += count;
+        },
+    );
+    writer.lock().unwrap().flush().expect("can't write edges");
+    info!("{} edges written to {}", edges, path);
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edges::*;
+    use crate::entity::EntitySeed;
+    use std::convert::TryInto;
+
+    const TOKYO: &str = r#"{"type":"item","id":"Q1490","claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":1200957,"id":"Q1200957"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"preferred"},{"mainsnak":{"snaktype":"somevalue","property":"P31","datatype":"wikibase-item"},"rank":"normal"}],"P1082":[{"mainsnak":{"snaktype":"value","property":"P1082","datavalue":{"value":{"amount":"+13960000","unit":"1"},"type":"quantity"},"datatype":"quantity"},"rank":"normal"}],"P17":[{"mainsnak":{"snaktype":"value","property":"P17","datavalue":{"value":{"entity-type":"item","numeric-id":17,"id":"Q17"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"}]}}"#;
+
+    fn tokyo() -> Entity<'static> {
+        Entity::parse(TOKYO.as_bytes(), EntitySeed::new(&[], None)).unwrap()
+    }
+
+    #[test]
+    fn check_tsv() {
+        let mut out = vec![];
+        assert_eq!(write_edges(&tokyo(), None, EdgeFormat::Tsv, &mut out), 2);
+        let mut lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec!["Q1490\tP17\tQ17\tnormal", "Q1490\tP31\tQ1200957\tpreferred"]
+        );
+
+        let mut out = vec![];
+        let properties = vec![String::from("P17")];
+        assert_eq!(
+            write_edges(&tokyo(), Some(&properties), EdgeFormat::Tsv, &mut out),
+            1
+        );
+        assert_eq!(out, b"Q1490\tP17\tQ17\tnormal\n");
+    }
+
+    #[test]
+    fn check_binary() {
+        let mut out = vec![];
+        let properties = vec![String::from("P31")];
+        let count = write_edges(&tokyo(), Some(&properties), EdgeFormat::Binary, &mut out);
+        assert_eq!(count, 1);
+        assert_eq!(out.len(), RECORD_BYTES);
+        let u64_at = |i: usize| u64::from_le_bytes(out[i..i + 8].try_into().unwrap());
+        assert_eq!(u64_at(0), (b'Q' as u64) << 56 | 1490);
+        assert_eq!(u64_at(8), (b'Q' as u64) << 56 | 1200957);
+        assert_eq!(&out[16..24], &[31, 0, 0, 0, 2, 0, 0, 0]);
+        assert!(EdgeFormat::parse("csv").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// An entity of the dump, deserialized lazily.
+///
+/// Only the requested languages and properties are kept, everything else is skipped while
+/// deserializing. Strings borrow from the line unless they contain escapes.
+#[derive(Debug, Default)]
+pub struct Entity<'a> {
+    pub id: Cow<'a, str>,
+    pub entity_type: Cow<'a, str>,
+    /// Datatype of a property entity, e.g. `external-id`. Empty for items.
+    pub datatype: Cow<'a, str>,
+    /// Language code to label.
+    pub labels: HashMap<Cow<'a, str>, Cow<'a, str>>,
+    /// Language code to description.
+    pub descriptions: HashMap<Cow<'a, str>, Cow<'a, str>>,
+    /// Language code to aliases.
+    pub aliases: HashMap<Cow<'a, str>, Vec<Cow<'a, str>>>,
+    /// Property id to statements.
+    pub claims: HashMap<Cow<'a, str>, Vec<Statement<'a>>>,
+    /// Site keys of the sitelinks, e.g. `jawiki`. Titles are skipped.
+    pub sitelinks: Vec<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Statement<'a> {
+    #[serde(borrow)]
+    pub mainsnak: Snak<'a>,
+    #[serde(borrow, default)]
+    pub rank: Cow<'a, str>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Snak<'a> {
+    #[serde(borrow, default)]
+    pub snaktype: Cow<'a, str>,
+    #[serde(borrow, default)]
+    pub datatype: Cow<'a, str>,
+    /// `None` for `novalue` and `somevalue` snaks.
+    #[serde(borrow, default)]
+    pub datavalue: Option<DataValue<'a>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DataValue<'a> {
+    pub value: Value,
+    #[serde(borrow, rename = "type")]
+    pub value_type: Cow<'a, str>,
+}
+
+impl<'a> Statement<'a> {
+    /// The target id of a `wikibase-entityid` value, e.g. `Q5`.
+    pub fn value_id(&self) -> Option<&str> {
+        self.mainsnak
+            .datavalue
+            .as_ref()
+            .and_then(|datavalue| datavalue.value.get("id"))
+            .and_then(|id| id.as_str())
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// Deserialize a line of the dump (without the trailing comma) with serde_json.
+    pub fn parse(article: &'a [u8], seed: EntitySeed) -> serde_json::Result<Entity<'a>> {
+        let mut deserializer = serde_json::Deserializer::from_slice(article);
+        let entity = seed.deserialize(&mut deserializer)?;
+        deserializer.end()?;
+        Ok(entity)
+    }
+
+    /// Deserialize a line of the dump (without the trailing comma) with simd-json.
+    /// `article` is used as scratch space and is garbage afterwards.
+    ///
+    /// Like `deserializer.end()` of `parse`, trailing input is an error: simd-json builds the
+    /// tape of the whole line before deserializing and fails on anything after the root value.
+    #[cfg(feature = "simd-json")]
+    pub fn parse_simd(article: &'a mut [u8], seed: EntitySeed) -> simd_json::Result<Entity<'a>> {
+        let mut deserializer = simd_json::Deserializer::from_slice(article)?;
+        seed.deserialize(&mut deserializer)
+    }
+}
+
+/// The top-level id of a dump line, found without parsing the JSON.
+/// Dumps write `"id"` before any nested object, so the first one is the entity id.
+pub fn peek_id(line: &str) -> Option<&str> {
+    let start = line.find("\"id\":")? + 5;
+    let value = line[start..].trim_start().strip_prefix('"')?;
+    let end = value.find('"')?;
+    Some(&value[..end])
+}
+
+/// Deserialize a line with the backend selected by the `simd-json` cargo feature.
+pub fn parse_entity<'a>(
+    article: &'a mut [u8],
+    seed: EntitySeed,
+) -> Result<Entity<'a>, Box<dyn Error + Send + Sync>> {
+    #[cfg(feature = "simd-json")]
+    return Ok(Entity::parse_simd(article, seed)?);
+    #[cfg(not(feature = "simd-json"))]
+    return Ok(Entity::parse(article, seed)?);
+}
+
+/// Selects the languages and properties that are kept in an `Entity`.
+#[derive(Debug, Clone, Copy)]
+pub struct EntitySeed<'c> {
+    /// `None` keeps all languages.
+    languages: Option<&'c [String]>,
+    /// `None` keeps all properties.
+    properties: Option<&'c [String]>,
+}
+
+impl<'c> EntitySeed<'c> {
+    pub fn new(languages: &'c [String], properties: Option<&'c [String]>) -> Self {
+        EntitySeed {
+            languages: Some(languages),
+            properties,
+        }
+    }
+
+    /// Keeps every language and property, e.g. to survey a whole dump.
+    pub fn all() -> Self {
+        EntitySeed {
+            languages: None,
+            properties: None,
+        }
+    }
+
+    /// `None` keeps all languages or all properties.
+    pub fn select(languages: Option<&'c [String]>, properties: Option<&'c [String]>) -> Self {
+        EntitySeed {
+            languages,
+            properties,
+        }
+    }
+
+    fn wants_language(&self, lang: &str) -> bool {
+        match self.languages {
+            Some(languages) => languages.iter().any(|x| x == lang),
+            None => true,
+        }
+    }
+
+    fn wants_property(&self, property: &str) -> bool {
+        match self.properties {
+            Some(properties) => properties.iter().any(|x| x == property),
+            None => true,
+        }
+    }
+}
+
+/// A string that borrows from the input when it has no escapes.
+#[derive(Deserialize)]
+struct Str<'a>(#[serde(borrow)] Cow<'a, str>);
+
+#[derive(Deserialize)]
+struct LangValue<'a> {
+    #[serde(borrow)]
+    value: Cow<'a, str>,
+}
+
+impl<'de, 'c> DeserializeSeed<'de> for EntitySeed<'c> {
+    type Value = Entity<'de>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Entity<'de>, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, 'c> Visitor<'de> for EntitySeed<'c> {
+    type Value = Entity<'de>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an entity object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Entity<'de>, A::Error> {
+        let mut entity = Entity::default();
+        while let Some(key) = map.next_key::<Str>()? {
+            match key.0.as_ref() {
+                "id" => entity.id = map.next_value::<Str>()?.0,
+                "type" => entity.entity_type = map.next_value::<Str>()?.0,
+                "datatype" => entity.datatype = map.next_value::<Str>()?.0,
+                "labels" => entity.labels = map.next_value_seed(LangSeed(self))?,
+                "descriptions" => entity.descriptions = map.next_value_seed(LangSeed(self))?,
+                "aliases" => entity.aliases = map.next_value_seed(AliasesSeed(self))?,
+                "claims" => entity.claims = map.next_value_seed(ClaimsSeed(self))?,
+                "sitelinks" => entity.sitelinks = map.next_value_seed(KeysSeed)?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(entity)
+    }
+}
+
+/// Empty objects may be written as `[]` in dumps, so the seeds below accept an empty array too.
+fn skip_seq<'de, A: SeqAccess<'de>>(mut seq: A) -> Result<(), A::Error> {
+    while seq.next_element::<IgnoredAny>()?.is_some() {}
+    Ok(())
+}
+
+struct LangSeed<'c>(EntitySeed<'c>);
+
+impl<'de, 'c> DeserializeSeed<'de> for LangSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Cow<'de, str>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'c> Visitor<'de> for LangSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Cow<'de, str>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of language values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = HashMap::new();
+        while let Some(key) = map.next_key::<Str>()? {
+            if self.0.wants_language(&key.0) {
+                values.insert(key.0, map.next_value::<LangValue>()?.value);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(values)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        skip_seq(seq)?;
+        Ok(HashMap::new())
+    }
+}
+
+struct AliasesSeed<'c>(EntitySeed<'c>);
+
+impl<'de, 'c> DeserializeSeed<'de> for AliasesSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Vec<Cow<'de, str>>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'c> Visitor<'de> for AliasesSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Vec<Cow<'de, str>>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of language value arrays")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut values = HashMap::new();
+        while let Some(key) = map.next_key::<Str>()? {
+            if self.0.wants_language(&key.0) {
+                let aliases = map.next_value::<Vec<LangValue>>()?;
+                values.insert(key.0, aliases.into_iter().map(|x| x.value).collect());
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(values)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        skip_seq(seq)?;
+        Ok(HashMap::new())
+    }
+}
+
+struct ClaimsSeed<'c>(EntitySeed<'c>);
+
+impl<'de, 'c> DeserializeSeed<'de> for ClaimsSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Vec<Statement<'de>>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'c> Visitor<'de> for ClaimsSeed<'c> {
+    type Value = HashMap<Cow<'de, str>, Vec<Statement<'de>>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map of claims")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut claims = HashMap::new();
+        while let Some(key) = map.next_key::<Str>()? {
+            if self.0.wants_property(&key.0) {
+                claims.insert(key.0, map.next_value::<Vec<Statement>>()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(claims)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        skip_seq(seq)?;
+        Ok(HashMap::new())
+    }
+}
+
+/// Keeps only the keys of a map.
+struct KeysSeed;
+
+impl<'de> DeserializeSeed<'de> for KeysSeed {
+    type Value = Vec<Cow<'de, str>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for KeysSeed {
+    type Value = Vec<Cow<'de, str>>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut keys = vec![];
+        while let Some(key) = map.next_key::<Str>()? {
+            map.next_value::<IgnoredAny>()?;
+            keys.push(key.0);
+        }
+        Ok(keys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+        skip_seq(seq)?;
+        Ok(vec![])
+    }
+}
@@ -0,0 +1,455 @@
+use crate::entity::Entity;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// An entity selection expression, e.g. `(P31=Q5 and has(P569)) or sitelink(jawiki)`.
+///
+/// ```text
+/// expr := term ("or" term)*
+/// term := factor ("and" factor)*
+/// factor := "not" factor | "(" expr ")" | PROPERTY "=" VALUE | PROPERTY "!=" VALUE
+///         | "has(" PROPERTY ")" | "sitelink(" SITE ")" | "type(" TYPE ")"
+/// ```
+///
+/// VALUE is an entity id such as `Q5` or a quoted string such as `"JP"`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    /// A value of the property is the entity id or the string.
+    Equals(String, Value),
+    Has(String),
+    Sitelink(String),
+    Type(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Id(String),
+    Str(String),
+}
+
+/// A parse error with the position in the expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    expr: String,
+    /// Character offset.
+    position: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    /// The message, the expression and a caret under the position.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} at column {}", self.message, self.position + 1)?;
+        writeln!(f, "  {}", self.expr)?;
+        write!(f, "  {}^", " ".repeat(self.position))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    Eq,
+    Ne,
+    Word(String),
+    Str(String),
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Eq => write!(f, "'='"),
+            Token::Ne => write!(f, "'!='"),
+            Token::Word(word) => write!(f, "'{}'", word),
+            Token::Str(string) => write!(f, "\"{}\"", string),
+            Token::End => write!(f, "end of expression"),
+        }
+    }
+}
+
+struct Parser<'a> {
+    expr: &'a str,
+    /// Tokens with their character offsets.
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+fn tokenize(expr: &str) -> Result<Vec<(Token, usize)>, ParseError> {
+    let chars: Vec<char> = expr.chars().collect();
+    let error = |position: usize, message: &str| ParseError {
+        expr: expr.to_string(),
+        position,
+        message: message.to_string(),
+    };
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let token = match chars[i] {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '=' => Token::Eq,
+            '!' if chars.get(i + 1) == Some(&'=') => {
+                i += 1;
+                Token::Ne
+            }
+            '"' => {
+                let mut string = String::new();
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                    }
+                    string.push(chars[i]);
+                    i += 1;
+                }
+                if i == chars.len() {
+                    return Err(error(start, "unterminated string"));
+                }
+                Token::Str(string)
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' || c == '.' => {
+                while i + 1 < chars.len()
+                    && (chars[i + 1].is_alphanumeric() || "_-.".contains(chars[i + 1]))
+                {
+                    i += 1;
+                }
+                Token::Word(chars[start..=i].iter().collect())
+            }
+            _ => return Err(error(start, "unexpected character")),
+        };
+        tokens.push((token, start));
+        i += 1;
+    }
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+fn is_property(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(chars.next(), Some('P') | Some('p'))
+        && !chars.as_str().is_empty()
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+/// `Q5`, `P31` or `L7`.
+fn is_entity_id(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!(
+        chars.next().map(|c| c.to_ascii_uppercase()),
+        Some('Q') | Some('P') | Some('L')
+    ) && !chars.as_str().is_empty()
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    match token {
+        Token::Word(word) => word.eq_ignore_ascii_case(keyword),
+        _ => false,
+    }
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        Err(ParseError {
+            expr: self.expr.to_string(),
+            position: self.tokens[self.next].1,
+            message,
+        })
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ParseError> {
+        if *self.peek() == expected {
+            self.advance();
+            return Ok(());
+        }
+        self.error(format!("expected {}, found {}", expected, self.peek()))
+    }
+
+    fn expr(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut left = self.term()?;
+        while is_keyword(self.peek(), "or") {
+            self.advance();
+            left = FilterExpr::Or(Box::new(left), Box::new(self.term()?));
+        }
+        Ok(left)
+    }
+
+    fn term(&mut self) -> Result<FilterExpr, ParseError> {
+        let mut left = self.factor()?;
+        while is_keyword(self.peek(), "and") {
+            self.advance();
+            left = FilterExpr::And(Box::new(left), Box::new(self.factor()?));
+        }
+        Ok(left)
+    }
+
+    fn factor(&mut self) -> Result<FilterExpr, ParseError> {
+        if is_keyword(self.peek(), "not") {
+            self.advance();
+            return Ok(FilterExpr::Not(Box::new(self.factor()?)));
+        }
+        match self.peek().clone() {
+            Token::LParen => {
+                self.advance();
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Token::Word(word) => {
+                let function = word.to_lowercase();
+                if ["has", "sitelink", "type"].contains(&function.as_str()) {
+                    self.advance();
+                    return self.function(&function);
+                }
+                if !is_property(&word) {
+                    return self.error(format!(
+                        "expected a property, has(), sitelink(), type() or '(', found '{}'",
+                        word
+                    ));
+                }
+                self.advance();
+                let property = word.to_uppercase();
+                let negated = match self.peek() {
+                    Token::Eq => false,
+                    Token::Ne => true,
+                    token => return self.error(format!("expected '=' or '!=', found {}", token)),
+                };
+                self.advance();
+                let value = match self.peek().clone() {
+                    Token::Word(id) if is_entity_id(&id) => Value::Id(id.to_uppercase()),
+                    Token::Str(string) => Value::Str(string),
+                    token => {
+                        return self.error(format!(
+                            "expected an entity id or a quoted string, found {}",
+                            token
+                        ))
+                    }
+                };
+                self.advance();
+                let equals = FilterExpr::Equals(property, value);
+                Ok(if negated {
+                    FilterExpr::Not(Box::new(equals))
+                } else {
+                    equals
+                })
+            }
+            token => self.error(format!("expected a condition, found {}", token)),
+        }
+    }
+
+    fn function(&mut self, function: &str) -> Result<FilterExpr, ParseError> {
+        self.expect(Token::LParen)?;
+        let argument = match self.peek().clone() {
+            Token::Word(word) => word,
+            token => {
+                return self.error(format!(
+                    "expected an argument of {}(), found {}",
+                    function, token
+                ))
+            }
+        };
+        let expr = match function {
+            "has" if is_property(&argument) => FilterExpr::Has(argument.to_uppercase()),
+            "has" => return self.error(format!("expected a property, found '{}'", argument)),
+            "sitelink" => FilterExpr::Sitelink(argument),
+            _ => match argument.to_lowercase().as_str() {
+                entity_type @ ("item" | "property" | "lexeme") => {
+                    FilterExpr::Type(entity_type.to_string())
+                }
+                _ => {
+                    return self.error(format!(
+                        "expected item, property or lexeme, found '{}'",
+                        argument
+                    ))
+                }
+            },
+        };
+        self.advance();
+        self.expect(Token::RParen)?;
+        Ok(expr)
+    }
+}
+
+impl FilterExpr {
+    pub fn parse(expr: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            expr,
+            tokens: tokenize(expr)?,
+            next: 0,
+        };
+        let filter = parser.expr()?;
+        if *parser.peek() != Token::End {
+            return parser.error(format!(
+                "expected 'and', 'or' or end of expression, found {}",
+                parser.peek()
+            ));
+        }
+        Ok(filter)
+    }
+
+    pub fn matches(&self, entity: &Entity) -> bool {
+        match self {
+            FilterExpr::And(left, right) => left.matches(entity) && right.matches(entity),
+            FilterExpr::Or(left, right) => left.matches(entity) || right.matches(entity),
+            FilterExpr::Not(expr) => !expr.matches(entity),
+            FilterExpr::Equals(property, value) => match entity.claims.get(property.as_str()) {
+                Some(statements) => statements.iter().any(|statement| match value {
+                    Value::Id(id) => statement.value_id() == Some(id.as_str()),
+                    Value::Str(string) => {
+                        statement
+                            .mainsnak
+                            .datavalue
+                            .as_ref()
+                            .and_then(|datavalue| datavalue.value.as_str())
+                            == Some(string.as_str())
+                    }
+                }),
+                None => false,
+            },
+            FilterExpr::Has(property) => entity.claims.contains_key(property.as_str()),
+            FilterExpr::Sitelink(site) => entity.sitelinks.iter().any(|x| x == site),
+            FilterExpr::Type(entity_type) => entity.entity_type == entity_type.as_str(),
+        }
+    }
+
+    /// Every property in the expression, they have to be deserialized.
+    pub fn properties(&self) -> BTreeSet<String> {
+        let mut properties = BTreeSet::new();
+        self.collect_properties(&mut properties);
+        properties
+    }
+
+    fn collect_properties(&self, properties: &mut BTreeSet<String>) {
+        match self {
+            FilterExpr::And(left, right) | FilterExpr::Or(left, right) => {
+                left.collect_properties(properties);
+                right.collect_properties(properties);
+            }
+            FilterExpr::Not(expr) => expr.collect_properties(properties),
+            FilterExpr::Equals(property, _) | FilterExpr::Has(property) => {
+                properties.insert(property.clone());
+            }
+            FilterExpr::Sitelink(_) | FilterExpr::Type(_) => {}
+        }
+    }
+
+    /// Properties that every matching entity has, used by the prefilter.
+    pub fn required_properties(&self) -> BTreeSet<String> {
+        match self {
+            FilterExpr::And(left, right) => {
+                let mut properties = left.required_properties();
+                properties.extend(right.required_properties());
+                properties
+            }
+            FilterExpr::Or(left, right) => left
+                .required_properties()
+                .intersection(&right.required_properties())
+                .cloned()
+                .collect(),
+            FilterExpr::Equals(property, _) | FilterExpr::Has(property) => {
+                let mut properties = BTreeSet::new();
+                properties.insert(property.clone());
+                properties
+            }
+            FilterExpr::Not(_) | FilterExpr::Sitelink(_) | FilterExpr::Type(_) => BTreeSet::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::{Entity, EntitySeed};
+    use crate::expr::*;
+
+    const HUMAN: &str = r#"{"type":"item","id":"Q1","claims":{
+        "P31":[{"mainsnak":{"datavalue":{"value":{"id":"Q5"},"type":"wikibase-entityid"}}}],
+        "P569":[{"mainsnak":{"datavalue":{"value":{"time":"+1900-01-01T00:00:00Z"},"type":"time"}}}],
+        "P297":[{"mainsnak":{"datavalue":{"value":"JP","type":"string"}}}]},
+        "sitelinks":{"enwiki":{"site":"enwiki","title":"X"}}}"#;
+
+    fn matches(expr: &str) -> bool {
+        let languages = vec![];
+        let entity = Entity::parse(HUMAN.as_bytes(), EntitySeed::new(&languages, None)).unwrap();
+        FilterExpr::parse(expr).unwrap().matches(&entity)
+    }
+
+    #[test]
+    fn check_evaluate() {
+        assert!(matches("(P31=Q5 and has(P569)) or sitelink(jawiki)"));
+        assert!(matches("p31=q5 AND NOT P31=Q6"));
+        assert!(matches("P31!=Q6 and type(item)"));
+        assert!(!matches("P31=Q5 and sitelink(jawiki)"));
+        assert!(matches("P31=Q6 or sitelink(enwiki)"));
+        assert!(matches(r#"P297="JP""#));
+        assert!(!matches("has(P18) or not has(P569)"));
+        assert!(matches("not (P31=Q6 or type(property))"));
+    }
+
+    #[test]
+    fn check_properties() {
+        let expr =
+            FilterExpr::parse("(P31=Q5 and has(P569)) or (P31=Q6 and not has(P18))").unwrap();
+        let properties: Vec<String> = expr.properties().into_iter().collect();
+        assert_eq!(properties, vec!["P18", "P31", "P569"]);
+        let required: Vec<String> = expr.required_properties().into_iter().collect();
+        assert_eq!(required, vec!["P31"]);
+    }
+
+    #[test]
+    fn check_parse_errors() {
+        let error = FilterExpr::parse("(P31=Q5 and has(P569)").unwrap_err();
+        assert_eq!(error.position, 21);
+        assert_eq!(
+            error.to_string(),
+            "expected ')', found end of expression at column 22\n  (P31=Q5 and has(P569)\n                       ^"
+        );
+        let error = FilterExpr::parse("P31=Q5 and has(Q5)").unwrap_err();
+        assert_eq!(error.position, 15);
+        let error = FilterExpr::parse("P31 Q5").unwrap_err();
+        assert_eq!(error.message, "expected '=' or '!=', found 'Q5'");
+        let error = FilterExpr::parse("label(ja)").unwrap_err();
+        assert_eq!(error.position, 0);
+        let error = FilterExpr::parse("P31=Q5 P17=Q17").unwrap_err();
+        assert_eq!(error.position, 7);
+        assert!(FilterExpr::parse("P31=").is_err());
+        assert!(FilterExpr::parse(r#"P297="JP"#).is_err());
+        assert!(FilterExpr::parse("P31=Q5 & P17=Q17").is_err());
+        let error = FilterExpr::parse("P297=JP").unwrap_err();
+        assert_eq!(error.position, 5);
+        assert_eq!(
+            error.message,
+            "expected an entity id or a quoted string, found 'JP'"
+        );
+        assert!(FilterExpr::parse("P1082=5").is_err());
+        assert!(FilterExpr::parse("P31=foo").is_err());
+        assert!(FilterExpr::parse("P5137=L7").is_ok());
+        let error = FilterExpr::parse("type(foo)").unwrap_err();
+        assert_eq!(error.position, 5);
+        assert!(FilterExpr::parse("type(Lexeme)").is_ok());
+    }
+}
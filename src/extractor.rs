@@ -0,0 +1,38 @@
+use crate::entity::Entity;
+use serde_json::{Map, Value};
+use std::fmt::Debug;
+
+/// A user-defined step of the per-entity processing.
+/// Registered extractors run in order after the built-in steps (id, labels, descriptions,
+/// aliases and claims).
+///
+/// ```
+/// use serde_json::{Map, Value};
+/// use wikidata_filter::entity::Entity;
+/// use wikidata_filter::extractor::Extractor;
+/// use wikidata_filter::parser::Config;
+///
+/// /// Popularity score from the number of sitelinks.
+/// #[derive(Debug)]
+/// struct SitelinkCount;
+///
+/// impl Extractor for SitelinkCount {
+///     fn extract(&self, entity: &Entity, output: &mut Map<String, Value>) {
+///         output.insert(String::from("sitelinks"), Value::from(entity.sitelinks.len()));
+///     }
+/// }
+///
+/// let config = Config::builder().extractor(SitelinkCount).build();
+/// ```
+pub trait Extractor: Debug + Send + Sync {
+    /// `entity` is the entity of the dump, `output` holds the fields written out.
+    /// `entity` has the language of the config, the properties of the config and the ones
+    /// returned by `properties`.
+    fn extract(&self, entity: &Entity, output: &mut Map<String, Value>);
+
+    /// Properties read by `extract` in addition to the properties of the config, e.g. `P1082`.
+    /// They are deserialized but not written out.
+    fn properties(&self) -> Vec<String> {
+        vec![]
+    }
+}
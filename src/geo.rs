@@ -0,0 +1,250 @@
+use crate::entity::Entity;
+use serde_json::Value;
+use std::fs;
+
+/// Mean radius of the Earth in km.
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+const EARTH: &str = "http://www.wikidata.org/entity/Q2";
+
+/// An area on the Earth. Coordinates are in degrees.
+#[derive(Debug, Clone)]
+pub enum GeoArea {
+    /// West may be greater than east for a box crossing the antimeridian.
+    BoundingBox {
+        west: f64,
+        south: f64,
+        east: f64,
+        north: f64,
+    },
+    Radius {
+        lat: f64,
+        lon: f64,
+        km: f64,
+    },
+    /// Polygons of `(lon, lat)` rings, the first ring of a polygon is the outline and the others
+    /// are holes. A point in any polygon is in the area.
+    Polygons(Vec<Vec<Vec<(f64, f64)>>>),
+}
+
+fn parse_numbers(value: &str, count: usize) -> Result<Vec<f64>, String> {
+    let numbers = value
+        .split(',')
+        .map(|x| x.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| format!("{}: {}", value, e))?;
+    if numbers.len() != count {
+        return Err(format!("{}: {} numbers expected", value, count));
+    }
+    Ok(numbers)
+}
+
+impl GeoArea {
+    /// `WEST,SOUTH,EAST,NORTH`, the order of a GeoJSON bbox.
+    pub fn bounding_box(value: &str) -> Result<Self, String> {
+        let x = parse_numbers(value, 4)?;
+        Ok(GeoArea::BoundingBox {
+            west: x[0],
+            south: x[1],
+            east: x[2],
+            north: x[3],
+        })
+    }
+
+    /// `LAT,LON,KM`
+    pub fn radius(value: &str) -> Result<Self, String> {
+        let x = parse_numbers(value, 3)?;
+        Ok(GeoArea::Radius {
+            lat: x[0],
+            lon: x[1],
+            km: x[2],
+        })
+    }
+
+    /// A GeoJSON Polygon or MultiPolygon, bare or in a Feature, FeatureCollection or
+    /// GeometryCollection.
+    pub fn geojson(json: &str) -> Result<Self, String> {
+        let value: Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        let mut polygons = vec![];
+        collect_polygons(&value, &mut polygons)?;
+        if polygons.is_empty() {
+            return Err(String::from("no Polygon or MultiPolygon in GeoJSON"));
+        }
+        Ok(GeoArea::Polygons(polygons))
+    }
+
+    pub fn load_geojson(path: &str) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        GeoArea::geojson(&json).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        match self {
+            GeoArea::BoundingBox {
+                west,
+                south,
+                east,
+                north,
+            } => {
+                let in_lon = if west <= east {
+                    *west <= lon && lon <= *east
+                } else {
+                    *west <= lon || lon <= *east
+                };
+                in_lon && *south <= lat && lat <= *north
+            }
+            GeoArea::Radius {
+                lat: center_lat,
+                lon: center_lon,
+                km,
+            } => haversine_km(*center_lat, *center_lon, lat, lon) <= *km,
+            GeoArea::Polygons(polygons) => polygons
+                .iter()
+                .any(|rings| rings.iter().filter(|ring| in_ring(ring, lon, lat)).count() % 2 == 1),
+        }
+    }
+}
+
+fn collect_polygons(value: &Value, polygons: &mut Vec<Vec<Vec<(f64, f64)>>>) -> Result<(), String> {
+    match value["type"].as_str() {
+        Some("FeatureCollection") => {
+            for feature in value["features"].as_array().into_iter().flatten() {
+                collect_polygons(feature, polygons)?;
+            }
+        }
+        Some("Feature") => collect_polygons(&value["geometry"], polygons)?,
+        Some("GeometryCollection") => {
+            for geometry in value["geometries"].as_array().into_iter().flatten() {
+                collect_polygons(geometry, polygons)?;
+            }
+        }
+        Some("Polygon") => polygons.push(polygon(&value["coordinates"])?),
+        Some("MultiPolygon") => {
+            for coordinates in value["coordinates"].as_array().into_iter().flatten() {
+                polygons.push(polygon(coordinates)?);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn polygon(coordinates: &Value) -> Result<Vec<Vec<(f64, f64)>>, String> {
+    let invalid = || format!("invalid polygon coordinates: {}", coordinates);
+    let mut rings = vec![];
+    for ring in coordinates.as_array().ok_or_else(invalid)? {
+        let mut points = vec![];
+        for point in ring.as_array().ok_or_else(invalid)? {
+            match (point[0].as_f64(), point[1].as_f64()) {
+                (Some(lon), Some(lat)) => points.push((lon, lat)),
+                _ => return Err(invalid()),
+            }
+        }
+        rings.push(points);
+    }
+    Ok(rings)
+}
+
+/// Ray casting, `ring` of `(x, y)`.
+fn in_ring(ring: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Keeps the entities with a coordinate of `property` in `area`.
+#[derive(Debug, Clone)]
+pub struct GeoFilter {
+    property: String,
+    area: GeoArea,
+}
+
+impl GeoFilter {
+    pub fn new(property: &str, area: GeoArea) -> Self {
+        GeoFilter {
+            property: property.to_uppercase(),
+            area,
+        }
+    }
+
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    /// `(lat, lon)` of the first coordinate on the Earth in the area.
+    pub fn locate(&self, entity: &Entity) -> Option<(f64, f64)> {
+        let statements = entity.claims.get(self.property.as_str())?;
+        statements
+            .iter()
+            .filter_map(|statement| statement.mainsnak.datavalue.as_ref())
+            .filter(|datavalue| datavalue.value_type == "globecoordinate")
+            .filter(|datavalue| match datavalue.value["globe"].as_str() {
+                Some(globe) => globe == EARTH,
+                None => true,
+            })
+            .filter_map(|datavalue| {
+                let lat = datavalue.value["latitude"].as_f64()?;
+                let lon = datavalue.value["longitude"].as_f64()?;
+                Some((lat, lon))
+            })
+            .find(|(lat, lon)| self.area.contains(*lat, *lon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo::*;
+
+    const TOKYO: (f64, f64) = (35.6812, 139.7671);
+    const OSAKA: (f64, f64) = (34.7025, 135.4959);
+    const SEOUL: (f64, f64) = (37.5665, 126.9780);
+
+    #[test]
+    fn check_bounding_box() {
+        let japan = GeoArea::bounding_box("122.9,24.0,153.99,45.6").unwrap();
+        assert!(japan.contains(TOKYO.0, TOKYO.1));
+        assert!(!japan.contains(-TOKYO.0, TOKYO.1));
+        let fiji = GeoArea::bounding_box("177,-21,-178,-12").unwrap();
+        assert!(fiji.contains(-18.1, 178.4));
+        assert!(fiji.contains(-16.5, -179.9));
+        assert!(!fiji.contains(-18.1, 170.0));
+        assert!(GeoArea::bounding_box("1,2,3").is_err());
+    }
+
+    #[test]
+    fn check_radius() {
+        assert!((haversine_km(TOKYO.0, TOKYO.1, OSAKA.0, OSAKA.1) - 403.0).abs() < 5.0);
+        let around_tokyo = GeoArea::radius("35.6812,139.7671,500").unwrap();
+        assert!(around_tokyo.contains(OSAKA.0, OSAKA.1));
+        assert!(!around_tokyo.contains(SEOUL.0, SEOUL.1));
+    }
+
+    #[test]
+    fn check_geojson() {
+        let json = r#"{"type":"FeatureCollection","features":[{"type":"Feature","properties":{},
+            "geometry":{"type":"Polygon","coordinates":[
+                [[130,30],[145,30],[145,40],[130,40],[130,30]],
+                [[139,35],[140,35],[140,36],[139,36],[139,35]]]}}]}"#;
+        let area = GeoArea::geojson(json).unwrap();
+        assert!(area.contains(OSAKA.0, OSAKA.1));
+        // in the hole
+        assert!(!area.contains(TOKYO.0, TOKYO.1));
+        assert!(!area.contains(SEOUL.0, SEOUL.1));
+        assert!(GeoArea::geojson(r#"{"type":"Point","coordinates":[1,2]}"#).is_err());
+    }
+}
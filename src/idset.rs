@@ -0,0 +1,131 @@
+use flate2::read::MultiGzDecoder;
+use log::{info, warn};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
+/// A set of entity ids, e.g. Q42 or P31, stored as sorted `u64`s (8 bytes per id).
+#[derive(Clone, Default)]
+pub struct IdSet {
+    ids: Vec<u64>,
+}
+
+impl fmt::Debug for IdSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IdSet({} ids)", self.ids.len())
+    }
+}
+
+/// The type letter in the top byte, the number below, e.g. `Q42` is `0x51 << 56 | 42`.
+pub(crate) fn encode(id: &str) -> Option<u64> {
+    let mut chars = id.chars();
+    let kind = chars.next()?.to_ascii_uppercase();
+    if !kind.is_ascii_uppercase() {
+        return None;
+    }
+    let number = chars.as_str().parse::<u64>().ok()?;
+    if number >= 1 << 56 {
+        return None;
+    }
+    Some((kind as u64) << 56 | number)
+}
+
+impl IdSet {
+    pub fn new<S: AsRef<str>>(ids: &[S]) -> Self {
+        let mut ids: Vec<u64> = ids.iter().filter_map(|id| encode(id.as_ref())).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        IdSet { ids }
+    }
+
+    /// Read one id per line from a plain text or gzip file. Entity URIs such as
+    /// `http://www.wikidata.org/entity/Q42` are accepted, empty lines and `#` comments are skipped.
+    pub fn load(path: &str) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+        let reader: Box<dyn Read> = if gzip {
+            Box::new(MultiGzDecoder::new(reader))
+        } else {
+            Box::new(reader)
+        };
+        let mut ids = vec![];
+        let mut invalid = 0;
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let id = line.rsplit('/').next().unwrap_or(line);
+            match encode(id) {
+                Some(id) => ids.push(id),
+                None => {
+                    if invalid == 0 {
+                        warn!("not an entity id in {}: {}", path, line);
+                    }
+                    invalid += 1;
+                }
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        info!(
+            "{} ids loaded from {} ({} invalid lines)",
+            ids.len(),
+            path,
+            invalid
+        );
+        Ok(IdSet { ids })
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        match encode(id) {
+            Some(id) => self.ids.binary_search(&id).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idset::*;
+    use crate::test_util::temp_path;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    #[test]
+    fn check_id_set() {
+        let ids = IdSet::new(&["Q42", "P31", "Q42", "q5", "L7", "Q1-broken"]);
+        assert_eq!(ids.len(), 4);
+        assert!(ids.contains("Q42"));
+        assert!(ids.contains("Q5"));
+        assert!(ids.contains("P31"));
+        assert!(!ids.contains("Q31"));
+        assert!(!ids.contains("P42"));
+        assert!(!ids.contains("L7-F1"));
+    }
+
+    #[test]
+    fn check_load_gzip() {
+        let path = temp_path("check_load_gzip.txt.gz");
+        let mut encoder = GzEncoder::new(File::create(&path).unwrap(), Compression::default());
+        encoder
+            .write_all(b"# people\nQ42\n\nhttp://www.wikidata.org/entity/Q1339\nnot an id\n")
+            .unwrap();
+        encoder.finish().unwrap();
+        let ids = IdSet::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains("Q42"));
+        assert!(ids.contains("Q1339"));
+    }
+}
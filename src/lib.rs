@@ -1,5 +1,28 @@
 extern crate serde;
 extern crate serde_derive;
 extern crate serde_json;
-mod output;
+pub mod date;
+pub mod decompress;
+pub mod dump_stats;
+pub mod edges;
+pub mod entity;
+pub mod expr;
+pub mod extractor;
+pub mod geo;
+pub mod idset;
+pub mod metrics;
+pub mod output;
 pub mod parser;
+mod prefilter;
+mod profile;
+mod progress;
+pub mod property_meta;
+pub mod quantity;
+pub mod sample;
+pub mod schema;
+pub mod sink;
+pub mod stats;
+pub mod stream;
+#[cfg(test)]
+mod test_util;
+pub mod text;
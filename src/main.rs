@@ -1,10 +1,14 @@
 #[macro_use]
 extern crate clap;
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use log::info;
 use metered::{measure, ResponseTime};
 use std::env;
+use wikidata_filter::dump_stats::scan_dump;
+use wikidata_filter::edges::{scan_edges, EdgeFormat};
 use wikidata_filter::parser::{parse_and_output, Config};
+use wikidata_filter::property_meta::scan_properties;
+use wikidata_filter::schema::scan_schema;
 
 fn main() {
     if env::var("RUST_LOG").is_err() {
@@ -14,6 +18,7 @@ fn main() {
     info!("Start!...");
     let app = App::new(crate_name!())
         .setting(AppSettings::DeriveDisplayOrder)
+        .setting(AppSettings::SubcommandsNegateReqs)
         .version(crate_version!())
         .author(crate_authors!())
         .about(crate_description!())
@@ -24,18 +29,18 @@ fn main() {
             Arg::with_name("INPUT_FILE")
                 .help("The file path of Wikidata dump JSON gzip, e.g. `latest-all.json.gz`. ")
                 .value_name("INPUT_FILE")
-                .required(true)
+                .required_unless("CONFIG")
                 .takes_value(true),
         )
         .arg(
             Arg::with_name("OUTPUT_PREFIX")
                 .help("The file prefix of output json files, e.g. `path/to/output_`. The command creates `path/to/output_01.json`")
                 .value_name("OUTPUT_PREFIX")
-                .required(true)
+                .required_unless("CONFIG")
                 .takes_value(true),
         ).arg(
         Arg::with_name("PROPERTIES")
-            .help("pass a comma-separated list of properties. E.g. p31,p21.")
+            .help("pass a comma-separated list of properties. E.g. p31,p21. The property keys only skip lines before JSON parsing with --require-properties.")
             .short("p")
             .long("properties")
             .required(false)
@@ -49,21 +54,416 @@ fn main() {
             .required(false)
             .takes_value(true)
         ).arg(
-        Arg::with_name("LIMITS")
-            .help("The limit number of reading lines from json file. If --limit is 100, the command only read first 100 lines. If set 0, the command proceed all lines.")
-            .long("limit")
+        Arg::with_name("MAX_LINES")
+            .help("The number of entity lines to read, not counting the enclosing [ and ] lines. If --max-lines is 100, the command only reads the first 100 entities (after --skip). If set 0, the command proceeds all lines.")
+            .long("max-lines")
+            .visible_alias("limit")
+            .default_value("0")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("MAX_EMITTED")
+            .help("Stop after this number of entities are written out. If set 0, no limit.")
+            .long("max-emitted")
+            .default_value("0")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("SKIP")
+            .help("Skip this number of entity lines without parsing them, e.g. to split the work across machines with --max-lines.")
+            .long("skip")
+            .default_value("0")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("SAMPLE_RATE")
+            .help("Keep this fraction of the entities, e.g. 0.01 for 1%, selected by a hash of the entity id. The same ids are selected from every dump version.")
+            .long("sample-rate")
+            .value_name("RATE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("SAMPLE_SIZE")
+            .help("Write out a uniform sample of this number of entities (reservoir sampling by the hash of the entity id). If set 0, no sampling.")
+            .long("sample-size")
+            .default_value("0")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("SAMPLE_SEED")
+            .help("Seed of the sampling hash of --sample-rate and --sample-size.")
+            .long("sample-seed")
             .default_value("0")
             .required(false)
-            .min_values(0)
             .takes_value(true)
+        ).arg(
+            Arg::with_name("INCLUDE_IDS")
+            .help("Only output the entities listed in this file, one id per line (plain text or gzip).")
+            .long("include-ids")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("EXCLUDE_IDS")
+            .help("Don't output the entities listed in this file, one id per line (plain text or gzip).")
+            .long("exclude-ids")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("GEO_BBOX")
+            .help("Only output entities located in this bounding box, e.g. 122.9,24.0,153.99,45.6 for Japan. lat and lon are added to the output.")
+            .long("geo-bbox")
+            .value_name("WEST,SOUTH,EAST,NORTH")
+            .allow_hyphen_values(true)
+            .required(false)
+            .takes_value(true)
+            .conflicts_with_all(&["GEO_RADIUS", "GEO_POLYGON"])
+        ).arg(
+            Arg::with_name("GEO_RADIUS")
+            .help("Only output entities located within KM kilometers of a point. lat and lon are added to the output.")
+            .long("geo-radius")
+            .value_name("LAT,LON,KM")
+            .allow_hyphen_values(true)
+            .required(false)
+            .takes_value(true)
+            .conflicts_with("GEO_POLYGON")
+        ).arg(
+            Arg::with_name("GEO_POLYGON")
+            .help("Only output entities located in the (Multi)Polygons of this GeoJSON file. lat and lon are added to the output.")
+            .long("geo-polygon")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("GEO_PROPERTY")
+            .help("The globe coordinate property of --geo-bbox, --geo-radius and --geo-polygon.")
+            .long("geo-property")
+            .default_value("P625")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("TIME_RANGE")
+            .help("Only output entities with a time value of PROPERTY between START and END (inclusive, either may be omitted), e.g. P569=1900..1950 or P571=..1850-06-30. Can be repeated.")
+            .long("time-range")
+            .value_name("PROPERTY=START..END")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+        ).arg(
+            Arg::with_name("CLAIM_DATES")
+            .help("Also write time values of --properties in the claims, as normalized dates (1830, 1830-05 or 1830-05-12). The claim arrays then mix entity ids and dates.")
+            .long("claim-dates")
+            .required(false)
+            .takes_value(false)
+        ).arg(
+            Arg::with_name("CLAIM_QUANTITIES")
+            .help("Also write quantity values of --properties in the claims, as {\"amount\",\"unit\",\"lower\",\"upper\"} objects. The claim arrays then mix entity ids and objects.")
+            .long("claim-quantities")
+            .required(false)
+            .takes_value(false)
+        ).arg(
+            Arg::with_name("FILTER")
+            .help("Only output entities matching this expression, e.g. \"(P31=Q5 and has(P569)) or sitelink(jawiki)\". Conditions: P=Q, P!=Q, P=\"string\", has(P), sitelink(SITE) and type(item), combined with and, or, not and parentheses.")
+            .long("filter")
+            .value_name("EXPR")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("TEXT_FILTER")
+            .help("Only output entities whose label, description or aliases in --language match, FIELD:KIND:PATTERN with FIELD labels, descriptions or aliases and KIND regex, prefix or script (Unicode script), e.g. labels:script:Han or descriptions:regex:^日本の. Can be repeated.")
+            .long("text-filter")
+            .value_name("FIELD:KIND:PATTERN")
+            .required(false)
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+        ).arg(
+            Arg::with_name("UNIT_TABLE")
+            .help("Convert the quantity values of --claim-quantities to canonical units with this tab separated table of FROM_UNIT, TO_UNIT, FACTOR and optional OFFSET, e.g. Q174728<TAB>Q11573<TAB>0.01 for centimetres to metres.")
+            .long("unit-table")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("REQUIRE_PROPERTIES")
+            .help("Only output entities that have claims of all --properties. Other entities are skipped before JSON parsing.")
+            .long("require-properties")
+            .required(false)
+            .takes_value(false)
+        ).arg(
+            Arg::with_name("THREADS")
+            .help("The number of parsing threads, and of decoding threads for parallel decompression. Default is the number of CPUs.")
+            .long("threads")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("CHUNK_SIZE")
+            .help("The number of lines handed to a worker thread at once.")
+            .long("chunk-size")
+            .default_value("100000")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("CHUNK_BYTES")
+            .help("Hand lines to a worker thread when they reach this size, e.g. 64M, even if --chunk-size is not reached. Bounds the memory per chunk.")
+            .long("chunk-bytes")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("PROGRESS_BAR")
+            .help("Render a progress bar on stderr when it is a terminal.")
+            .long("progress-bar")
+            .required(false)
+            .takes_value(false)
+        ).arg(
+            Arg::with_name("STATS_FILE")
+            .help("Write statistics of the run as JSON to this file, e.g. lines read, entities emitted and per-property value counts.")
+            .long("stats-file")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("METRICS_FILE")
+            .help("Export lines read, entities emitted, errors and bytes read to this node-exporter textfile in the Prometheus format. Rewritten every 1,000,000 lines and at the end.")
+            .long("metrics-file")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("STAGE_METRICS")
+            .help("Measure throughput and response time of each stage (read, skip_parse, parse, copy_*, flush) and log them every 1,000,000 lines.")
+            .long("stage-metrics")
+            .required(false)
+            .takes_value(false)
+        ).arg(
+            Arg::with_name("CONFIG")
+            .help("TOML config file with named profiles. Options given on the command line override the profile values.")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true)
+        ).arg(
+            Arg::with_name("PROFILE")
+            .help("The profile name in the config file, e.g. `search-ja`.")
+            .long("profile")
+            .default_value("default")
+            .required(false)
+            .takes_value(true)
+        ).subcommand(
+            SubCommand::with_name("stats")
+            .about("Scans the whole dump and reports per-property usage, per-language label and description coverage, entity types and the top P31 classes.")
+            .args(&scan_args())
+            .arg(
+                Arg::with_name("FORMAT")
+                .help("Print a human-readable table or the JSON report to stdout.")
+                .long("format")
+                .possible_values(&["table", "json"])
+                .default_value("table")
+                .required(false)
+                .takes_value(true)
+            ).arg(
+                Arg::with_name("TOP")
+                .help("The number of P31 classes in the report, and of properties and languages in the table.")
+                .long("top")
+                .default_value("20")
+                .required(false)
+                .takes_value(true)
+            )
+        ).subcommand(
+            SubCommand::with_name("schema")
+            .about("Scans the whole dump and prints a JSON catalogue of the properties: datatype, datavalue type, snak type counts and example values.")
+            .args(&scan_args())
+            .arg(
+                Arg::with_name("EXAMPLES")
+                .help("The number of distinct example values per property.")
+                .long("examples")
+                .default_value("3")
+                .required(false)
+                .takes_value(true)
+            )
+        ).subcommand(
+            SubCommand::with_name("properties")
+            .about("Prints a JSON dictionary of the property entities: labels, datatype, formatter URLs (P1630) and constraint types (P2302).")
+            .args(&scan_args())
+            .arg(
+                Arg::with_name("LANGUAGES")
+                .help("A comma-separated list of label languages, e.g. en,ja.")
+                .short("l")
+                .long("languages")
+                .default_value("en")
+                .required(false)
+                .takes_value(true)
+            )
+        ).subcommand(
+            SubCommand::with_name("edges")
+            .about("Writes the claims whose values are entity ids as an edge list of source, property, target and rank.")
+            .args(&scan_args())
+            .arg(
+                Arg::with_name("OUTPUT_FILE")
+                .help("The file path of the edge list, e.g. `edges.tsv`.")
+                .value_name("OUTPUT_FILE")
+                .required(true)
+                .takes_value(true)
+            ).arg(
+                Arg::with_name("PROPERTIES")
+                .help("pass a comma-separated list of properties. E.g. p31,p279. Default is all properties.")
+                .short("p")
+                .long("properties")
+                .required(false)
+                .takes_value(true)
+            ).arg(
+                Arg::with_name("FORMAT")
+                .help("tsv: one `source<TAB>property<TAB>target<TAB>rank` line per edge. binary: 24-byte little-endian records, see the README.")
+                .long("format")
+                .possible_values(&["tsv", "binary"])
+                .default_value("tsv")
+                .required(false)
+                .takes_value(true)
+            )
         );
 
-    let config = Config::new(app.get_matches());
+    let args = app.get_matches();
+    if let (name, Some(args)) = args.subcommand() {
+        return subcommand(name, args);
+    }
+    let config = Config::new(args);
     let ref response_time: ResponseTime = ResponseTime::default();
     info!("{:?}", config);
-    measure!(response_time, {
-        parse_and_output(&config);
-    });
+    let report = measure!(response_time, { parse_and_output(&config) });
+    info!(
+        "{} lines read, {} entities emitted, {} errors",
+        report.lines_read, report.entities_emitted, report.errors
+    );
     info!("Finish!...");
     info!("{}", serde_json::to_string(&response_time).unwrap());
 }
+
+/// Arguments of the subcommands that scan the whole dump.
+fn scan_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("INPUT_FILE")
+            .help("The file path of Wikidata dump JSON gzip, e.g. `latest-all.json.gz`. ")
+            .value_name("INPUT_FILE")
+            .required_unless("CONFIG")
+            .takes_value(true),
+        Arg::with_name("MAX_LINES")
+            .help("The number of entity lines to read. If set 0, the command proceeds all lines.")
+            .long("max-lines")
+            .visible_alias("limit")
+            .default_value("0")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("THREADS")
+            .help("The number of worker threads for decompression and parsing. Default is the number of CPUs.")
+            .long("threads")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("CHUNK_SIZE")
+            .help("The number of lines handed to a worker thread at once.")
+            .long("chunk-size")
+            .default_value("100000")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("CHUNK_BYTES")
+            .help("Hand lines to a worker thread when they reach this size, e.g. 64M, even if --chunk-size is not reached. Bounds the memory per chunk.")
+            .long("chunk-bytes")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("SKIP")
+            .help("Skip this number of entity lines without parsing them.")
+            .long("skip")
+            .default_value("0")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("PROGRESS_BAR")
+            .help("Render a progress bar on stderr when it is a terminal.")
+            .long("progress-bar")
+            .required(false)
+            .takes_value(false),
+        Arg::with_name("CONFIG")
+            .help("TOML config file with named profiles. The input and the reader options of the profile are used, options given on the command line override them.")
+            .short("c")
+            .long("config")
+            .value_name("FILE")
+            .required(false)
+            .takes_value(true),
+        Arg::with_name("PROFILE")
+            .help("The profile name in the config file, e.g. `search-ja`.")
+            .long("profile")
+            .default_value("default")
+            .required(false)
+            .takes_value(true),
+    ]
+}
+
+/// Scan the dump with the reader settings of `args` and print the output of the subcommand.
+fn subcommand(name: &str, args: &ArgMatches) {
+    let config = Config::scan(args);
+    info!("{:?}", config);
+    let output = match name {
+        "stats" => stats(&config, args),
+        "schema" => schema(&config, args),
+        "properties" => properties(&config, args),
+        "edges" => edges(&config, args),
+        _ => unreachable!(),
+    };
+    print!("{}", output);
+    info!("Finish!...");
+}
+
+fn stats(config: &Config, args: &ArgMatches) -> String {
+    let top = args
+        .value_of("TOP")
+        .unwrap()
+        .parse::<usize>()
+        .expect("--top must be a number");
+    let report = scan_dump(config, top);
+    match args.value_of("FORMAT") {
+        Some("json") => report.to_json_string() + "\n",
+        _ => report.to_table(top),
+    }
+}
+
+fn schema(config: &Config, args: &ArgMatches) -> String {
+    let examples = args
+        .value_of("EXAMPLES")
+        .unwrap()
+        .parse::<usize>()
+        .expect("--examples must be a number");
+    let catalogue = scan_schema(config, examples);
+    serde_json::to_string_pretty(&catalogue).expect("to_json_string Error...") + "\n"
+}
+
+fn properties(config: &Config, args: &ArgMatches) -> String {
+    let languages: Vec<String> = args
+        .value_of("LANGUAGES")
+        .unwrap()
+        .split(",")
+        .filter(|x| !x.is_empty())
+        .map(String::from)
+        .collect();
+    let dictionary = scan_properties(config, &languages);
+    serde_json::to_string_pretty(&dictionary).expect("to_json_string Error...") + "\n"
+}
+
+/// The edges go to OUTPUT_FILE, nothing is printed.
+fn edges(config: &Config, args: &ArgMatches) -> String {
+    let properties: Option<Vec<String>> = args.value_of("PROPERTIES").map(|prop_str| {
+        prop_str
+            .split(",")
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_uppercase())
+            .collect()
+    });
+    let format = EdgeFormat::parse(args.value_of("FORMAT").unwrap()).unwrap();
+    scan_edges(
+        config,
+        properties,
+        format,
+        args.value_of("OUTPUT_FILE").unwrap(),
+    );
+    String::new()
+}
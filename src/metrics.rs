@@ -0,0 +1,118 @@
+use metered::clear::Clear;
+use metered::hdr_histogram::AtomicHdrHistogram;
+use metered::metric::Histogram;
+use metered::time_source::StdInstantMicros;
+use metered::{measure, ResponseTime, Throughput};
+use serde_derive::Serialize;
+use std::fmt;
+use std::sync::Arc;
+
+/// Throughput and response time histogram of one stage of the pipeline.
+/// Response times are in microseconds, most stages take well under a millisecond per line.
+#[derive(Default, Serialize)]
+pub struct StageMetrics {
+    pub throughput: Throughput,
+    pub response_time: ResponseTime<MicrosHistogram, StdInstantMicros>,
+}
+
+/// `AtomicHdrHistogram` bounded to 5 minutes in microseconds. `ResponseTime` asks for a bound of
+/// 5 minutes in milliseconds, which would saturate at 300ms with a microsecond time source.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct MicrosHistogram(AtomicHdrHistogram);
+
+impl Histogram for MicrosHistogram {
+    fn with_bound(_max_value: u64) -> Self {
+        MicrosHistogram(AtomicHdrHistogram::with_bound(5 * 60 * 1_000_000))
+    }
+
+    fn record(&self, value: u64) {
+        self.0.record(value);
+    }
+}
+
+impl Clear for MicrosHistogram {
+    fn clear(&self) {
+        self.0.clear();
+    }
+}
+
+impl StageMetrics {
+    pub fn measure<T, F: FnOnce() -> T>(&self, f: F) -> T {
+        let throughput = &self.throughput;
+        let response_time = &self.response_time;
+        measure!(throughput, measure!(response_time, f()))
+    }
+}
+
+/// Per-stage metrics of a run, shared by the reader thread and the workers.
+/// Enabled with `--stage-metrics`, dumped as JSON with the progress log.
+#[derive(Default, Serialize)]
+pub struct PipelineMetrics {
+    /// Reading (and decompressing) one line.
+    pub read: StageMetrics,
+    pub skip_parse: StageMetrics,
+    /// JSON parsing of one entity.
+    pub parse: StageMetrics,
+    pub copy_id: StageMetrics,
+    pub copy_labels: StageMetrics,
+    pub copy_desc: StageMetrics,
+    pub copy_aliases: StageMetrics,
+    pub copy_claims: StageMetrics,
+    pub extractors: StageMetrics,
+    /// Flushing one shard of the output.
+    pub flush: StageMetrics,
+}
+
+impl fmt::Debug for PipelineMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PipelineMetrics")
+    }
+}
+
+/// Run `f`, measured by `stage` if the metrics are enabled.
+pub(crate) fn measure_stage<T, F: FnOnce() -> T>(
+    metrics: &Option<Arc<PipelineMetrics>>,
+    stage: fn(&PipelineMetrics) -> &StageMetrics,
+    f: F,
+) -> T {
+    match metrics {
+        Some(metrics) => stage(metrics).measure(f),
+        None => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn check_measure_stage() {
+        let metrics = Some(Arc::new(PipelineMetrics::default()));
+        assert_eq!(measure_stage(&metrics, |m| &m.parse, || 1 + 1), 2);
+        assert_eq!(measure_stage(&None, |m| &m.parse, || 1 + 1), 2);
+        let json = serde_json::to_value(metrics.as_deref().unwrap()).unwrap();
+        assert!(json["parse"]["response_time"].is_object());
+        assert!(json["copy_claims"]["throughput"].is_object());
+    }
+
+    #[test]
+    fn check_response_time_micros() {
+        let metrics = PipelineMetrics::default();
+        metrics.parse.measure(|| {
+            let start = Instant::now();
+            while start.elapsed() < Duration::from_micros(300) {}
+        });
+        metrics
+            .flush
+            .measure(|| std::thread::sleep(Duration::from_millis(400)));
+        let json = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(json["parse"]["response_time"]["samples"], 1);
+        let parse = json["parse"]["response_time"]["max"].as_u64().unwrap();
+        assert!((300..300_000).contains(&parse), "{}", parse);
+        // longer than the 300ms the default bound would allow in microseconds
+        let flush = json["flush"]["response_time"]["max"].as_u64().unwrap();
+        assert!(flush >= 400_000, "{}", flush);
+    }
+}
@@ -1,10 +1,14 @@
+use crate::parser::Document;
+use crate::sink::{Sink, SinkShard};
 use log::debug;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
 
+/// Writes every shard to its own file, `<path_prefix>_<N>.json`, one document per line.
 pub struct OutputManager {
     path_prefix: String,
     file_counter: i32,
+    file_paths: Vec<String>,
 }
 
 impl OutputManager {
@@ -12,11 +16,13 @@ impl OutputManager {
         OutputManager {
             path_prefix: path_prefix.to_string(),
             file_counter: 0,
+            file_paths: vec![],
         }
     }
     pub fn create_output_json(&mut self) -> OutputJson {
         let file_path = format!("{}_{}.json", &self.path_prefix, self.file_counter);
         self.file_counter += 1;
+        self.file_paths.push(file_path.clone());
         return OutputJson::new(file_path.as_str());
     }
 }
@@ -54,3 +60,25 @@ impl OutputJson {
         debug!("Finish {} flush...", self.file_name);
     }
 }
+
+impl Sink for OutputManager {
+    type Shard = OutputJson;
+
+    fn open_shard(&mut self) -> OutputJson {
+        self.create_output_json()
+    }
+
+    fn output_files(&self) -> Vec<String> {
+        self.file_paths.clone()
+    }
+}
+
+impl SinkShard for OutputJson {
+    fn write(&mut self, doc: Document) {
+        self.output(doc.to_json_string());
+    }
+
+    fn flush(&mut self) {
+        OutputJson::flush(self);
+    }
+}
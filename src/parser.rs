@@ -1,280 +1,1219 @@
-use crate::output::{OutputJson, OutputManager};
-use clap::ArgMatches;
+use crate::date::{TimeRange, WikidataTime};
+use crate::decompress::open_input;
+use crate::entity::{parse_entity, peek_id, Entity, EntitySeed, Statement};
+use crate::expr::FilterExpr;
+use crate::extractor::Extractor;
+use crate::geo::{GeoArea, GeoFilter};
+use crate::idset::IdSet;
+use crate::metrics::{measure_stage, PipelineMetrics};
+use crate::output::OutputManager;
+use crate::prefilter::Prefilter;
+use crate::profile::Profile;
+use crate::progress::{CountingReader, Progress};
+use crate::quantity::{Quantity, UnitTable};
+use crate::sample::{id_hash, keep_by_rate, Reservoir};
+use crate::sink::{Sink, SinkShard};
+use crate::stats::{ChunkStats, RunStats, StatsReport};
+use crate::text::TextFilter;
+use clap::{ArgMatches, ErrorKind};
 use core::result::Result::{Err, Ok};
-use flate2::read::MultiGzDecoder;
 use futures::executor::{block_on, ThreadPool};
 use futures::task::SpawnExt;
 use log::{debug, info, warn};
-use regex::Regex;
 use serde_json::value::Value::Array;
 use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::slice;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Clone)]
 pub struct Config {
     input_file: String,
     output_prefix: String,
+    threads: usize,
     chunk_size: usize,
+    chunk_bytes: usize,
     properties: Vec<String>,
+    /// `properties` and the properties read by the filters and the extractors.
+    seed_properties: Vec<String>,
     lang: String,
-    with_limiter: bool,
-    limit: u64,
-    lang_regex: Regex,
+    pub(crate) skip: u64,
+    pub(crate) max_lines: u64,
+    pub(crate) max_emitted: u64,
+    sample_rate: Option<f64>,
+    sample_size: usize,
+    sample_seed: u64,
+    include_ids: Option<Arc<IdSet>>,
+    exclude_ids: Option<Arc<IdSet>>,
+    geo_filter: Option<GeoFilter>,
+    time_ranges: Vec<TimeRange>,
+    claim_dates: bool,
+    claim_quantities: bool,
+    unit_table: Option<Arc<UnitTable>>,
+    text_filters: Vec<TextFilter>,
+    filter: Option<FilterExpr>,
+    progress_bar: bool,
+    stats_file: Option<String>,
+    metrics_file: Option<String>,
+    metrics: Option<Arc<PipelineMetrics>>,
+    require_properties: bool,
+    prefilter: Prefilter,
+    extractors: Vec<Arc<dyn Extractor>>,
 }
 
 impl Config {
+    /// Per-stage metrics, if enabled by `ConfigBuilder::stage_metrics`.
+    pub fn metrics(&self) -> Option<&PipelineMetrics> {
+        self.metrics.as_deref()
+    }
+
+    /// Languages and properties that are deserialized from each entity.
+    pub(crate) fn entity_seed(&self) -> EntitySeed<'_> {
+        EntitySeed::new(slice::from_ref(&self.lang), Some(&self.seed_properties))
+    }
+
+    /// Build a config for a subcommand that scans the whole dump, e.g. `stats`. Only the input
+    /// and the reader settings are used, from the command line or the `--config` profile.
+    pub fn scan(args: &ArgMatches) -> Self {
+        let profile = load_profile(args);
+        let input_file = resolve_arg(args, "INPUT_FILE", profile.input_file.clone())
+            .expect("INPUT_FILE is required either on the command line or in the profile");
+        Config::builder()
+            .input_file(&input_file)
+            .reader_args(args, &profile)
+            .build()
+    }
+
+    /// Build a config from the command line. When `--config` is given, the selected profile
+    /// fills every setting that is not passed explicitly on the command line.
     pub fn new(args: ArgMatches) -> Self {
-        let input_file = args.value_of("INPUT_FILE").unwrap();
-        let output_prefix = args.value_of("OUTPUT_PREFIX").unwrap();
-        let lang = args.value_of("LANGUAGE").unwrap();
-        let prop_str = args.value_of("PROPERTIES").unwrap();
-        let properties = prop_str
-            .split(",")
-            .map(|x| x.to_uppercase().to_string())
-            .collect();
-        let limit_str = args.value_of("LIMITS").unwrap();
-        let limit = u64::from_str(limit_str).expect("aa");
-        return Config {
-            input_file: input_file.to_string(),
-            output_prefix: output_prefix.to_string(),
+        let profile = load_profile(&args);
+        let reader = Config::builder().reader_args(&args, &profile);
+        let input_file = resolve_arg(&args, "INPUT_FILE", profile.input_file)
+            .expect("INPUT_FILE is required either on the command line or in the profile");
+        let output_prefix = resolve_arg(&args, "OUTPUT_PREFIX", profile.output_prefix)
+            .expect("OUTPUT_PREFIX is required either on the command line or in the profile");
+        let lang = resolve_arg(&args, "LANGUAGE", profile.language).unwrap();
+        let properties: Vec<String> = match explicit_arg(&args, "PROPERTIES") {
+            Some(prop_str) => prop_str
+                .split(",")
+                .filter(|x| !x.is_empty())
+                .map(String::from)
+                .collect(),
+            None => profile.properties.unwrap_or_default(),
+        };
+        let mut builder = reader
+            .input_file(&input_file)
+            .output_prefix(&output_prefix)
+            .properties(&properties)
+            .language(&lang)
+            .max_emitted(count_arg(
+                &args,
+                "MAX_EMITTED",
+                "--max-emitted",
+                profile.max_emitted,
+            ))
+            .stage_metrics(args.is_present("STAGE_METRICS"))
+            .require_properties(
+                args.is_present("REQUIRE_PROPERTIES")
+                    || profile.require_properties.unwrap_or(false),
+            );
+        let sample_rate = match explicit_arg(&args, "SAMPLE_RATE") {
+            Some(rate) => Some(f64::from_str(rate).expect("--sample-rate must be a number")),
+            None => profile.sample_rate,
+        };
+        if let Some(rate) = sample_rate {
+            builder = builder.sample_rate(rate);
+        }
+        builder = builder
+            .sample_size(
+                count_arg(&args, "SAMPLE_SIZE", "--sample-size", profile.sample_size) as usize,
+            )
+            .sample_seed(count_arg(
+                &args,
+                "SAMPLE_SEED",
+                "--sample-seed",
+                profile.sample_seed,
+            ));
+        if let Some(path) = resolve_arg(&args, "INCLUDE_IDS", profile.include_ids) {
+            let ids =
+                IdSet::load(&path).unwrap_or_else(|_| panic!("can't read ids file[{}]", path));
+            builder = builder.include_ids(ids);
+        }
+        if let Some(path) = resolve_arg(&args, "EXCLUDE_IDS", profile.exclude_ids) {
+            let ids =
+                IdSet::load(&path).unwrap_or_else(|_| panic!("can't read ids file[{}]", path));
+            builder = builder.exclude_ids(ids);
+        }
+        let geo_area = if let Some(bbox) = resolve_arg(&args, "GEO_BBOX", profile.geo_bbox) {
+            Some(GeoArea::bounding_box(&bbox).expect("--geo-bbox must be WEST,SOUTH,EAST,NORTH"))
+        } else if let Some(radius) = resolve_arg(&args, "GEO_RADIUS", profile.geo_radius) {
+            Some(GeoArea::radius(&radius).expect("--geo-radius must be LAT,LON,KM"))
+        } else {
+            resolve_arg(&args, "GEO_POLYGON", profile.geo_polygon).map(|path| {
+                GeoArea::load_geojson(&path).expect("--geo-polygon must be a GeoJSON file")
+            })
+        };
+        if let Some(geo_area) = geo_area {
+            let property = resolve_arg(&args, "GEO_PROPERTY", profile.geo_property).unwrap();
+            builder = builder.geo_filter(GeoFilter::new(&property, geo_area));
+        }
+        let time_ranges: Vec<String> = match args.values_of("TIME_RANGE") {
+            Some(values) => values.map(String::from).collect(),
+            None => profile.time_ranges.unwrap_or_default(),
+        };
+        for time_range in &time_ranges {
+            builder = builder.time_range(
+                TimeRange::parse(time_range)
+                    .expect("--time-range must be PROPERTY=START..END, e.g. P569=1900..1950"),
+            );
+        }
+        builder = builder
+            .claim_dates(args.is_present("CLAIM_DATES") || profile.claim_dates.unwrap_or(false))
+            .claim_quantities(
+                args.is_present("CLAIM_QUANTITIES") || profile.claim_quantities.unwrap_or(false),
+            );
+        let text_filters: Vec<String> = match args.values_of("TEXT_FILTER") {
+            Some(values) => values.map(String::from).collect(),
+            None => profile.text_filters.unwrap_or_default(),
+        };
+        for text_filter in &text_filters {
+            builder =
+                builder
+                    .text_filter(TextFilter::parse(text_filter).expect(
+                        "--text-filter must be FIELD:KIND:PATTERN, e.g. labels:script:Han",
+                    ));
+        }
+        if let Some(filter) = resolve_arg(&args, "FILTER", profile.filter) {
+            match FilterExpr::parse(&filter) {
+                Ok(filter) => builder = builder.filter(filter),
+                Err(e) => clap::Error::with_description(
+                    &format!("Invalid --filter: {}", e),
+                    ErrorKind::InvalidValue,
+                )
+                .exit(),
+            }
+        }
+        if let Some(path) = resolve_arg(&args, "UNIT_TABLE", profile.unit_table) {
+            builder = builder.unit_table(UnitTable::load(&path).expect("Error in unit table"));
+        }
+        if let Some(stats_file) = resolve_arg(&args, "STATS_FILE", profile.stats_file) {
+            builder = builder.stats_file(&stats_file);
+        }
+        if let Some(metrics_file) = resolve_arg(&args, "METRICS_FILE", profile.metrics_file) {
+            builder = builder.metrics_file(&metrics_file);
+        }
+        builder.build()
+    }
+
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+}
+
+/// Builds a `Config` without clap, for using this crate as a library.
+///
+/// ```
+/// use wikidata_filter::parser::Config;
+///
+/// let config = Config::builder()
+///     .language("en")
+///     .properties(&["p31", "p279"])
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    input_file: String,
+    output_prefix: String,
+    threads: usize,
+    chunk_size: usize,
+    chunk_bytes: usize,
+    properties: Vec<String>,
+    lang: String,
+    skip: u64,
+    max_lines: u64,
+    max_emitted: u64,
+    sample_rate: Option<f64>,
+    sample_size: usize,
+    sample_seed: u64,
+    include_ids: Option<Arc<IdSet>>,
+    exclude_ids: Option<Arc<IdSet>>,
+    geo_filter: Option<GeoFilter>,
+    time_ranges: Vec<TimeRange>,
+    claim_dates: bool,
+    claim_quantities: bool,
+    unit_table: Option<Arc<UnitTable>>,
+    text_filters: Vec<TextFilter>,
+    filter: Option<FilterExpr>,
+    progress_bar: bool,
+    stats_file: Option<String>,
+    metrics_file: Option<String>,
+    stage_metrics: bool,
+    require_properties: bool,
+    extractors: Vec<Arc<dyn Extractor>>,
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        ConfigBuilder::new()
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        ConfigBuilder {
+            input_file: String::new(),
+            output_prefix: String::new(),
+            threads: 0,
             chunk_size: 100000,
-            properties,
-            lang: lang.to_string(),
-            with_limiter: limit > 0,
-            limit,
-            lang_regex: Regex::new(format!("\"{}\"", lang).as_str()).unwrap(),
+            chunk_bytes: 0,
+            properties: vec![],
+            lang: String::from("ja"),
+            skip: 0,
+            max_lines: 0,
+            max_emitted: 0,
+            sample_rate: None,
+            sample_size: 0,
+            sample_seed: 0,
+            include_ids: None,
+            exclude_ids: None,
+            geo_filter: None,
+            time_ranges: vec![],
+            claim_dates: false,
+            claim_quantities: false,
+            unit_table: None,
+            text_filters: vec![],
+            filter: None,
+            progress_bar: false,
+            stats_file: None,
+            metrics_file: None,
+            stage_metrics: false,
+            require_properties: false,
+            extractors: vec![],
+        }
+    }
+
+    /// Only needed by `parse_and_output`. The streaming API takes a reader instead.
+    pub fn input_file(mut self, input_file: &str) -> Self {
+        self.input_file = input_file.to_string();
+        self
+    }
+
+    /// Only needed by `parse_and_output`.
+    pub fn output_prefix(mut self, output_prefix: &str) -> Self {
+        self.output_prefix = output_prefix.to_string();
+        self
+    }
+
+    /// The number of worker threads. 0 means the number of CPUs.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The number of lines handed to a worker at once.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Hand the lines to a worker when they reach this size in bytes, even if the chunk has fewer
+    /// than `chunk_size` lines. 0 means no limit.
+    pub fn chunk_bytes(mut self, chunk_bytes: usize) -> Self {
+        self.chunk_bytes = chunk_bytes;
+        self
+    }
+
+    /// Claim properties to copy, e.g. `["P31", "P279"]`. Case insensitive.
+    pub fn properties<S: AsRef<str>>(mut self, properties: &[S]) -> Self {
+        self.properties = properties
+            .iter()
+            .map(|x| x.as_ref().to_uppercase())
+            .collect();
+        self
+    }
+
+    /// Wikimedia language code of labels, descriptions and aliases.
+    pub fn language(mut self, lang: &str) -> Self {
+        self.lang = lang.to_string();
+        self
+    }
+
+    /// Skip the first `skip` entity lines without parsing them.
+    /// The `[` and `]` lines of the dump are not counted.
+    pub fn skip(mut self, skip: u64) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Stop after `max_lines` entity lines following the skipped ones. 0 means all lines.
+    pub fn max_lines(mut self, max_lines: u64) -> Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    /// Same as `max_lines`.
+    pub fn limit(self, limit: u64) -> Self {
+        self.max_lines(limit)
+    }
+
+    /// Stop after `max_emitted` entities are written out. 0 means no limit.
+    /// With several threads, which entities make it is not necessarily the first ones of the dump.
+    pub fn max_emitted(mut self, max_emitted: u64) -> Self {
+        self.max_emitted = max_emitted;
+        self
+    }
+
+    /// Keep this fraction of the entities, e.g. 0.01 for 1%, selected by a hash of the entity id
+    /// and `sample_seed`. The same ids are selected from every dump version.
+    pub fn sample_rate(mut self, sample_rate: f64) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Write out a uniform sample of `sample_size` entities, those with the smallest hashes of
+    /// the entity id and `sample_seed`. Only used by `parse_and_output`. 0 means no sampling.
+    pub fn sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Seed of the sampling hash. Another seed selects another sample.
+    pub fn sample_seed(mut self, sample_seed: u64) -> Self {
+        self.sample_seed = sample_seed;
+        self
+    }
+
+    /// Only output the entities in `ids`.
+    pub fn include_ids(mut self, ids: IdSet) -> Self {
+        self.include_ids = Some(Arc::new(ids));
+        self
+    }
+
+    /// Don't output the entities in `ids`.
+    pub fn exclude_ids(mut self, ids: IdSet) -> Self {
+        self.exclude_ids = Some(Arc::new(ids));
+        self
+    }
+
+    /// Only output entities located in an area, with their `lat` and `lon` as numbers.
+    pub fn geo_filter(mut self, geo_filter: GeoFilter) -> Self {
+        self.geo_filter = Some(geo_filter);
+        self
+    }
+
+    /// Only output entities with a time value in the range. Can be called several times, then
+    /// every range must match.
+    pub fn time_range(mut self, time_range: TimeRange) -> Self {
+        self.time_ranges.push(time_range);
+        self
+    }
+
+    /// Also write time values of `properties` in the claims, as normalized dates. Off by default,
+    /// since it mixes date strings into the arrays of entity ids.
+    pub fn claim_dates(mut self, claim_dates: bool) -> Self {
+        self.claim_dates = claim_dates;
+        self
+    }
+
+    /// Also write quantity values of `properties` in the claims, as
+    /// `{"amount","unit","lower","upper"}` objects. Off by default, since it mixes objects into
+    /// the arrays of entity ids.
+    pub fn claim_quantities(mut self, claim_quantities: bool) -> Self {
+        self.claim_quantities = claim_quantities;
+        self
+    }
+
+    /// Convert quantity values to canonical units, see `claim_quantities`.
+    pub fn unit_table(mut self, unit_table: UnitTable) -> Self {
+        self.unit_table = Some(Arc::new(unit_table));
+        self
+    }
+
+    /// Only output entities whose label, description or aliases match. Can be called several
+    /// times, then every filter must match.
+    pub fn text_filter(mut self, text_filter: TextFilter) -> Self {
+        self.text_filters.push(text_filter);
+        self
+    }
+
+    /// Only output entities matching the expression, see `FilterExpr`.
+    pub fn filter(mut self, filter: FilterExpr) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Render a progress bar on stderr when it is a terminal.
+    pub fn progress_bar(mut self, progress_bar: bool) -> Self {
+        self.progress_bar = progress_bar;
+        self
+    }
+
+    /// Write the statistics of the run as JSON to this file.
+    pub fn stats_file(mut self, stats_file: &str) -> Self {
+        self.stats_file = Some(stats_file.to_string());
+        self
+    }
+
+    /// Export the run counters to this node-exporter textfile (Prometheus format).
+    /// It is rewritten every 1,000,000 lines and at the end of the run.
+    pub fn metrics_file(mut self, metrics_file: &str) -> Self {
+        self.metrics_file = Some(metrics_file.to_string());
+        self
+    }
+
+    /// Measure each stage of the pipeline with metered, see `Config::metrics`.
+    pub fn stage_metrics(mut self, stage_metrics: bool) -> Self {
+        self.stage_metrics = stage_metrics;
+        self
+    }
+
+    /// Only output entities that have claims of all `properties`.
+    pub fn require_properties(mut self, require_properties: bool) -> Self {
+        self.require_properties = require_properties;
+        self
+    }
+
+    /// Add a custom extraction step. Steps run in the order they are added.
+    pub fn extractor<E: Extractor + 'static>(mut self, extractor: E) -> Self {
+        self.extractors.push(Arc::new(extractor));
+        self
+    }
+
+    fn prefilter_patterns(&self) -> Vec<String> {
+        let mut patterns = vec![Prefilter::lang_pattern(&self.lang)];
+        if self.require_properties {
+            for property in &self.properties {
+                patterns.push(Prefilter::property_pattern(property));
+            }
+        }
+        if let Some(geo_filter) = &self.geo_filter {
+            patterns.push(Prefilter::property_pattern(geo_filter.property()));
+        }
+        for time_range in &self.time_ranges {
+            patterns.push(Prefilter::property_pattern(time_range.property()));
+        }
+        if let Some(filter) = &self.filter {
+            for property in filter.required_properties() {
+                patterns.push(Prefilter::property_pattern(&property));
+            }
+        }
+        patterns
+    }
+
+    fn seed_properties(&self) -> Vec<String> {
+        let mut properties = self.properties.clone();
+        if let Some(geo_filter) = &self.geo_filter {
+            properties.push(geo_filter.property().to_string());
+        }
+        for time_range in &self.time_ranges {
+            properties.push(time_range.property().to_string());
+        }
+        if let Some(filter) = &self.filter {
+            properties.extend(filter.properties());
+        }
+        for extractor in &self.extractors {
+            properties.extend(extractor.properties());
+        }
+        properties.sort();
+        properties.dedup();
+        properties
+    }
+
+    /// The dump reader settings of the command line or the profile: `--threads`,
+    /// `--chunk-size`, `--chunk-bytes`, `--skip`, `--max-lines` and `--progress-bar`.
+    fn reader_args(mut self, args: &ArgMatches, profile: &Profile) -> Self {
+        self = self.threads(match explicit_arg(args, "THREADS") {
+            Some(threads) => usize::from_str(threads).expect("--threads must be a number"),
+            None => profile.threads.unwrap_or(0),
+        });
+        let chunk_size = match explicit_arg(args, "CHUNK_SIZE") {
+            Some(size) => Some(usize::from_str(size).expect("--chunk-size must be a number")),
+            None => profile.chunk_size,
         };
+        if let Some(chunk_size) = chunk_size {
+            self = self.chunk_size(chunk_size);
+        }
+        self.chunk_bytes(match explicit_arg(args, "CHUNK_BYTES") {
+            Some(size) => parse_bytes(size).expect("--chunk-bytes must be a size, e.g. 64M"),
+            None => profile
+                .chunk_bytes
+                .as_ref()
+                .map(|size| parse_bytes(size).expect("chunk_bytes must be a size, e.g. 64M"))
+                .unwrap_or(0),
+        })
+        .skip(count_arg(args, "SKIP", "--skip", profile.skip))
+        .max_lines(count_arg(
+            args,
+            "MAX_LINES",
+            "--max-lines",
+            profile.max_lines,
+        ))
+        .progress_bar(args.is_present("PROGRESS_BAR"))
+    }
+
+    pub fn build(self) -> Config {
+        let prefilter = Prefilter::new(&self.prefilter_patterns());
+        let seed_properties = self.seed_properties();
+        Config {
+            input_file: self.input_file,
+            output_prefix: self.output_prefix,
+            threads: if self.threads > 0 {
+                self.threads
+            } else {
+                default_threads()
+            },
+            chunk_size: self.chunk_size,
+            chunk_bytes: self.chunk_bytes,
+            properties: self.properties,
+            seed_properties,
+            prefilter,
+            require_properties: self.require_properties,
+            lang: self.lang,
+            skip: self.skip,
+            max_lines: self.max_lines,
+            max_emitted: self.max_emitted,
+            sample_rate: self.sample_rate,
+            sample_size: self.sample_size,
+            sample_seed: self.sample_seed,
+            include_ids: self.include_ids,
+            exclude_ids: self.exclude_ids,
+            geo_filter: self.geo_filter,
+            time_ranges: self.time_ranges,
+            claim_dates: self.claim_dates,
+            claim_quantities: self.claim_quantities,
+            unit_table: self.unit_table,
+            text_filters: self.text_filters,
+            filter: self.filter,
+            progress_bar: self.progress_bar,
+            stats_file: self.stats_file,
+            metrics_file: self.metrics_file,
+            metrics: if self.stage_metrics {
+                Some(Arc::new(PipelineMetrics::default()))
+            } else {
+                None
+            },
+            extractors: self.extractors,
+        }
+    }
+}
+
+/// The number of CPUs, the default number of worker threads.
+pub(crate) fn default_threads() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+/// A size in bytes with an optional `K`, `M` or `G` suffix, e.g. `64M`.
+fn parse_bytes(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let (number, unit) = match size.char_indices().last() {
+        Some((i, 'K')) | Some((i, 'k')) => (&size[..i], 1 << 10),
+        Some((i, 'M')) | Some((i, 'm')) => (&size[..i], 1 << 20),
+        Some((i, 'G')) | Some((i, 'g')) => (&size[..i], 1 << 30),
+        _ => (size, 1),
+    };
+    usize::from_str(number)
+        .ok()
+        .and_then(|number| number.checked_mul(unit))
+}
+
+/// The value of `name` only if the user passed it on the command line, ignoring clap defaults.
+fn explicit_arg<'a>(args: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    if args.occurrences_of(name) > 0 {
+        args.value_of(name)
+    } else {
+        None
     }
 }
 
-#[derive(Debug)]
+/// The `--profile` of the `--config` file, or an empty profile.
+fn load_profile(args: &ArgMatches) -> Profile {
+    match args.value_of("CONFIG") {
+        Some(path) => Profile::load(path, args.value_of("PROFILE").unwrap()),
+        None => Profile::default(),
+    }
+}
+
+/// Command line count first, then the profile value, then 0.
+fn count_arg(args: &ArgMatches, name: &str, flag: &str, profile_value: Option<u64>) -> u64 {
+    match explicit_arg(args, name) {
+        Some(count) => u64::from_str(count).unwrap_or_else(|_| panic!("{} must be a number", flag)),
+        None => profile_value.unwrap_or(0),
+    }
+}
+
+/// Command line value first, then the profile value, then the clap default.
+fn resolve_arg(args: &ArgMatches, name: &str, from_profile: Option<String>) -> Option<String> {
+    explicit_arg(args, name)
+        .map(String::from)
+        .or(from_profile)
+        .or(args.value_of(name).map(String::from))
+}
+
+#[derive(Debug, Default)]
 pub struct Document {
-    original_map: Map<String, Value>,
     new_map: Map<String, Value>,
 }
 
 impl Document {
+    pub fn new() -> Self {
+        Document {
+            new_map: Map::new(),
+        }
+    }
+
     pub fn to_json_string(&self) -> String {
         return serde_json::to_string(&self.new_map).expect("to_json_string Error...");
     }
 
-    pub fn copy_id(&mut self) {
-        let value = self.original_map.get("id").unwrap().clone();
-        &self.new_map.insert(String::from("id"), value);
+    /// The entity id, e.g. `Q31`.
+    pub fn id(&self) -> Option<&str> {
+        self.new_map.get("id").and_then(|id| id.as_str())
     }
 
-    pub fn copy_labels(&mut self, config: &Config) {
-        self.copy_lang_value(config, "labels");
+    /// The filtered fields that are written to the output.
+    pub fn fields(&self) -> &Map<String, Value> {
+        &self.new_map
     }
-    pub fn copy_desc(&mut self, config: &Config) {
-        self.copy_lang_value(config, "descriptions");
+
+    pub fn into_fields(self) -> Map<String, Value> {
+        self.new_map
     }
-    pub fn copy_aliases(&mut self, config: &Config) {
-        self.copy_lang_values(config, "aliases");
+
+    pub fn copy_id(&mut self, entity: &Entity) {
+        self.new_map
+            .insert(String::from("id"), Value::from(entity.id.as_ref()));
     }
 
-    fn copy_clone_ids(&self, item: &Value, clone_ids: &mut Vec<Value>) {
-        let map = item.as_object().expect("Claim object parse error...");
-        if let Some(mainsnak) = map.get("mainsnak") {
-            let mainsnak_map = mainsnak
-                .as_object()
-                .expect("Mainsnak object parse error...");
-            if let Some(datavalue) = mainsnak_map.get("datavalue") {
-                let datavalue_map = datavalue
-                    .as_object()
-                    .expect("Datavalue object parse error...");
-                if let Some(value) = datavalue_map.get("value") {
-                    let value_map = value.as_object().expect("Value object parse error...");
-                    if let Some(id) = value_map.get("id") {
-                        clone_ids.push(id.clone());
-                    }
-                }
+    pub fn copy_labels(&mut self, entity: &Entity, config: &Config) {
+        self.copy_lang_value(&entity.labels, config, "labels");
+    }
+    pub fn copy_desc(&mut self, entity: &Entity, config: &Config) {
+        self.copy_lang_value(&entity.descriptions, config, "descriptions");
+    }
+    pub fn copy_aliases(&mut self, entity: &Entity, config: &Config) {
+        if let Some(aliases) = entity.aliases.get(config.lang.as_str()) {
+            if !aliases.is_empty() {
+                let values: Vec<Value> = aliases.iter().map(|x| Value::from(x.as_ref())).collect();
+                self.new_map
+                    .insert(String::from("aliases"), Value::from(values));
             }
         }
     }
 
-    pub fn copy_claims(&mut self, config: &Config) {
-        if let Some(obj) = self.original_map.get("claims") {
-            let map = obj
-                .as_object()
-                .expect(format!("Error during converting \"claims\" to map").as_str());
-            let mut copied_claims = Map::new();
-            for property in &config.properties {
-                if let Some(claim) = map.get(property) {
-                    let mut clone_ids = vec![];
-                    let prop_array = claim.as_array().expect(
-                        format!("Error during converting \"{}\" to array", property).as_str(),
-                    );
-                    for item in prop_array {
-                        //measure_ns!({
-                        &self.copy_clone_ids(item, &mut clone_ids);
-                        //});
-                    }
-                    if !clone_ids.is_empty() {
-                        copied_claims.insert(property.to_string(), Array(clone_ids));
-                    }
-                }
+    /// Entity ids, normalized dates with `claim_dates` and quantities with `claim_quantities`.
+    /// Other values are skipped.
+    fn copy_clone_ids(&self, item: &Statement, config: &Config, clone_ids: &mut Vec<Value>) {
+        if let Some(id) = item.value_id() {
+            clone_ids.push(Value::from(id));
+        } else if let Some(time) = WikidataTime::from_statement(item) {
+            if config.claim_dates {
+                clone_ids.push(Value::from(time.to_date_string()));
             }
-            if !copied_claims.is_empty() {
-                self.new_map.insert(
-                    String::from("claims"),
-                    serde_json::to_value(copied_claims).expect("to_value error..."),
-                );
+        } else if let Some(quantity) = Quantity::from_statement(item) {
+            if config.claim_quantities {
+                let quantity = match &config.unit_table {
+                    Some(unit_table) => unit_table.convert(quantity),
+                    None => quantity,
+                };
+                clone_ids.push(quantity.to_json());
             }
         }
     }
 
-    fn copy_lang_value(&mut self, config: &Config, key: &str) {
-        if let Some(obj) = self.original_map.get(key) {
-            let map = obj
-                .as_object()
-                .expect(format!("Error during converting \"{}\" to map", key).as_str());
-            if let Some(lang) = map.get(&config.lang) {
-                let lang_map = lang
-                    .as_object()
-                    .expect("Error during converting \"lang\" to map");
-                if let Some(lang_value) = lang_map.get("value") {
-                    &self.new_map.insert(String::from(key), lang_value.clone());
+    pub fn copy_claims(&mut self, entity: &Entity, config: &Config) {
+        let mut copied_claims = Map::new();
+        for property in &config.properties {
+            if let Some(statements) = entity.claims.get(property.as_str()) {
+                let mut clone_ids = vec![];
+                for item in statements {
+                    self.copy_clone_ids(item, config, &mut clone_ids);
+                }
+                if !clone_ids.is_empty() {
+                    copied_claims.insert(property.to_string(), Array(clone_ids));
                 }
             }
         }
+        if !copied_claims.is_empty() {
+            self.new_map
+                .insert(String::from("claims"), Value::Object(copied_claims));
+        }
     }
 
-    fn copy_lang_values(&mut self, config: &Config, key: &str) {
-        if let Some(obj) = self.original_map.get(key) {
-            let map = obj
-                .as_object()
-                .expect(format!("Error during converting \"{}\" to map", key).as_str());
-            if let Some(lang) = map.get(&config.lang) {
-                let lang_array = lang
-                    .as_array()
-                    .expect("Error during converting \"lang\" to array");
-
-                let values: Vec<Value> = lang_array
-                    .iter()
-                    .filter_map(|item| {
-                        let lang_map = item
-                            .as_object()
-                            .expect("Error during converting \"lang\" to map");
-                        if let Some(lang_value) = lang_map.get("value") {
-                            Some(lang_value.clone())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                if values.len() > 0 {
-                    &self.new_map.insert(String::from(key), Value::from(values));
+    fn copy_lang_value(
+        &mut self,
+        values: &HashMap<Cow<str>, Cow<str>>,
+        config: &Config,
+        key: &str,
+    ) {
+        if let Some(lang_value) = values.get(config.lang.as_str()) {
+            self.new_map
+                .insert(String::from(key), Value::from(lang_value.as_ref()));
+        }
+    }
+}
+
+/// All `properties` of the config are in the claims of the entity.
+fn has_properties(entity: &Entity, config: &Config) -> bool {
+    config
+        .properties
+        .iter()
+        .all(|property| entity.claims.contains_key(property.as_str()))
+}
+
+/// `output` is `None` in reservoir mode, the sample is written by `write_sample` at the end.
+async fn process_buffer<S: SinkShard>(
+    buffer: Vec<String>,
+    config: Config,
+    mut output: Option<S>,
+    stats: Arc<RunStats>,
+    reservoir: Option<Arc<Reservoir>>,
+) {
+    debug!("start process_buffer...");
+    let mut chunk_stats = ChunkStats::default();
+    for article in buffer {
+        if stats.emitted_limit_reached(config.max_emitted) {
+            break;
+        }
+        if let Some(reservoir) = &reservoir {
+            let hash = peek_id(&article).map(|id| id_hash(config.sample_seed, id));
+            if !hash.map(|hash| reservoir.may_keep(hash)).unwrap_or(true) {
+                continue;
+            }
+            if let Some(doc) = parse_article(article, &config, &mut chunk_stats) {
+                if let Some(id) = doc.id() {
+                    reservoir.offer(id_hash(config.sample_seed, id), doc);
                 }
             }
+            continue;
+        }
+        if let Some(doc) = parse_article(article, &config, &mut chunk_stats) {
+            if !stats.reserve_emitted(config.max_emitted) {
+                break;
+            }
+            if let Some(output) = &mut output {
+                chunk_stats.record_emitted(&doc);
+                output.write(doc);
+            }
         }
     }
+    if let Some(output) = &mut output {
+        measure_stage(&config.metrics, |m| &m.flush, || output.flush());
+    }
+    stats.add_chunk(chunk_stats);
+    debug!("finish process_buffer...");
 }
 
-async fn process_buffer(buffer: Vec<String>, config: Config, mut output: OutputJson) {
-    debug!("start process_buffer...");
-    for mut article in buffer {
-        //TODO 最後の行の処理
-        let last = article.pop().unwrap();
-        if last != ',' {
-            article.push(last);
-        }
-        let mut doc = Document {
-            original_map: serde_json::from_str(article.as_str())
-                .expect("something wrong during parsing json"),
-            new_map: Map::new(),
-        };
-        process_doc(&mut doc, &config);
-        output.output(doc.to_json_string());
+/// Returns `None` if the entity doesn't pass the filters of `config` or can't be parsed.
+pub(crate) fn parse_article(
+    article: String,
+    config: &Config,
+    stats: &mut ChunkStats,
+) -> Option<Document> {
+    let mut article = entity_bytes(article);
+    let entity = match measure_stage(
+        &config.metrics,
+        |m| &m.parse,
+        || parse_entity(&mut article, config.entity_seed()),
+    ) {
+        Ok(entity) => entity,
+        Err(e) => {
+            warn!("something wrong during parsing json: {}", e);
+            stats.errors += 1;
+            return None;
+        }
+    };
+    stats.entities_parsed += 1;
+    if config.require_properties && !has_properties(&entity, config) {
+        return None;
+    }
+    if !config
+        .time_ranges
+        .iter()
+        .all(|range| range.matches(&entity))
+    {
+        return None;
+    }
+    let location = match &config.geo_filter {
+        Some(geo_filter) => Some(geo_filter.locate(&entity)?),
+        None => None,
+    };
+    let mut doc = Document::new();
+    if !process_doc(&entity, &mut doc, config) {
+        return None;
+    }
+    if let Some((lat, lon)) = location {
+        doc.new_map.insert(String::from("lat"), Value::from(lat));
+        doc.new_map.insert(String::from("lon"), Value::from(lon));
+    }
+    Some(doc)
+}
+
+/// Write the reservoir to one shard, after all the workers are done.
+fn write_sample<S: Sink>(reservoir: &Reservoir, config: &Config, sink: &mut S, stats: &RunStats) {
+    let mut output = sink.open_shard();
+    let mut chunk_stats = ChunkStats::default();
+    for doc in reservoir.take_documents() {
+        if !stats.reserve_emitted(config.max_emitted) {
+            break;
+        }
+        chunk_stats.record_emitted(&doc);
+        output.write(doc);
     }
     output.flush();
-    debug!("finish process_buffer...");
+    stats.add_chunk(chunk_stats);
+}
+
+/// The JSON of an entity line, without the trailing comma.
+fn entity_bytes(article: String) -> Vec<u8> {
+    let mut article = article.into_bytes();
+    //TODO 最後の行の処理
+    let last = article.pop().unwrap();
+    if last != b',' {
+        article.push(last);
+    }
+    article
+}
+
+/// Every line of the dump except the enclosing `[` and `]`.
+pub(crate) fn is_entity_line(line: &str) -> bool {
+    let line = line.trim();
+    !(line.is_empty() || line == "[" || line == "]")
 }
 
-fn skip_parse(article: &str, config: &Config) -> bool {
-    // need lang chars in article
-    // TODO check properties?
-    return !config.lang_regex.is_match(article);
+fn log_metrics(config: &Config) {
+    if let Some(metrics) = &config.metrics {
+        info!(
+            "stage metrics: {}",
+            serde_json::to_string(metrics.as_ref()).expect("to_json_string Error...")
+        );
+    }
 }
 
-pub fn parse_and_output(config: &Config) {
+pub(crate) fn skip_parse(article: &str, config: &Config) -> bool {
+    measure_stage(
+        &config.metrics,
+        |m| &m.skip_parse,
+        || {
+            if skip_by_id(article, config) {
+                return true;
+            }
+            // need lang chars in article, and property keys if required
+            !config.prefilter.is_match(article)
+        },
+    )
+}
+
+/// Id lists and sampling, checked on the id at the start of the line.
+fn skip_by_id(article: &str, config: &Config) -> bool {
+    if config.include_ids.is_none() && config.exclude_ids.is_none() && config.sample_rate.is_none()
+    {
+        return false;
+    }
+    let id = match peek_id(article) {
+        Some(id) => id,
+        None => return config.include_ids.is_some() || config.sample_rate.is_some(),
+    };
+    if let Some(include_ids) = &config.include_ids {
+        if !include_ids.contains(id) {
+            return true;
+        }
+    }
+    if let Some(exclude_ids) = &config.exclude_ids {
+        if exclude_ids.contains(id) {
+            return true;
+        }
+    }
+    if let Some(rate) = config.sample_rate {
+        if !keep_by_rate(id_hash(config.sample_seed, id), rate) {
+            return true;
+        }
+    }
+    false
+}
+
+pub fn parse_and_output(config: &Config) -> StatsReport {
+    let mut output_manager = OutputManager::new(&config.output_prefix);
+    parse_and_output_with(config, &mut output_manager)
+}
+
+/// Same as `parse_and_output`, but writes the documents to `sink` instead of json files.
+pub fn parse_and_output_with<S: Sink>(config: &Config, sink: &mut S) -> StatsReport {
+    let pool = thread_pool(config);
+    let stats = Arc::new(RunStats::default());
+    let reservoir = if config.sample_size > 0 {
+        Some(Arc::new(Reservoir::new(config.sample_size)))
+    } else {
+        None
+    };
+    let mut futures = vec![];
+    let bytes_read = read_chunks(
+        config,
+        &stats,
+        |article| !skip_parse(article, config),
+        |buffer| {
+            futures.push(
+                pool.spawn_with_handle(process_buffer(
+                    buffer,
+                    config.clone(),
+                    if reservoir.is_none() {
+                        Some(sink.open_shard())
+                    } else {
+                        None
+                    },
+                    stats.clone(),
+                    reservoir.clone(),
+                ))
+                .expect("Spawn error..."),
+            );
+        },
+    );
+    debug!("before block_on...");
+    block_on(futures::future::join_all(futures));
+    if let Some(reservoir) = reservoir {
+        write_sample(&reservoir, config, sink, &stats);
+    }
+    sink.finish();
+    debug!("finish block_on...");
+    stats.add_output_files(sink.output_files());
+    let report = stats.report();
+    if let Some(metrics_file) = &config.metrics_file {
+        stats.write_prometheus(metrics_file, bytes_read);
+    }
+    if let Some(stats_file) = &config.stats_file {
+        info!("write stats to {}...", stats_file);
+        report.write(stats_file);
+    }
+    report
+}
+
+/// Read the entity lines of the dump in chunks and run `scan` on each chunk in the thread pool.
+/// The lines are read as in `parse_and_output_with`, lines that don't pass `keep` are skipped.
+/// Returns the counters of the reading.
+fn scan_chunks<K, F>(config: &Config, keep: K, scan: F) -> StatsReport
+where
+    K: Fn(&str) -> bool,
+    F: Fn(Vec<String>) + Send + Sync + 'static,
+{
+    let pool = thread_pool(config);
+    let stats = RunStats::default();
+    let scan = Arc::new(scan);
+    let mut futures = vec![];
+    read_chunks(config, &stats, keep, |buffer| {
+        let scan = scan.clone();
+        futures.push(
+            pool.spawn_with_handle(async move { scan(buffer) })
+                .expect("Spawn error..."),
+        );
+    });
+    block_on(futures::future::join_all(futures));
+    stats.report()
+}
+
+/// Parse the entity lines of the dump that pass `keep` and fold the entities into one `T`, for
+/// the subcommands. `languages` and `properties` select what is parsed, `None` keeps them all.
+/// Each chunk `record`s its entities into a `new()` value which is then `merge`d into the
+/// total. Returns the total and the counters of the run, parse errors included.
+pub(crate) fn scan_entities<T, K, N, R, M>(
+    config: &Config,
+    languages: Option<Vec<String>>,
+    properties: Option<Vec<String>>,
+    keep: K,
+    new: N,
+    record: R,
+    merge: M,
+) -> (T, StatsReport)
+where
+    T: Send + 'static,
+    K: Fn(&str) -> bool,
+    N: Fn() -> T + Send + Sync + 'static,
+    R: Fn(&Entity, &mut T) + Send + Sync + 'static,
+    M: Fn(&mut T, T) + Send + Sync + 'static,
+{
+    let total = Arc::new(Mutex::new(Some(new())));
+    let parsed = Arc::new(AtomicU64::new(0));
+    let errors = Arc::new(AtomicU64::new(0));
+    let shared = (total.clone(), parsed.clone(), errors.clone());
+    let mut report = scan_chunks(config, keep, move |buffer| {
+        let (total, parsed, errors) = &shared;
+        let seed = EntitySeed::select(languages.as_deref(), properties.as_deref());
+        let mut chunk = new();
+        for article in buffer {
+            let mut article = entity_bytes(article);
+            match parse_entity(&mut article, seed) {
+                Ok(entity) => {
+                    parsed.fetch_add(1, Ordering::Relaxed);
+                    record(&entity, &mut chunk);
+                }
+                Err(e) => {
+                    warn!("something wrong during parsing json: {}", e);
+                    errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        merge(total.lock().unwrap().as_mut().unwrap(), chunk);
+    });
+    report.entities_parsed = parsed.load(Ordering::Relaxed);
+    report.errors += errors.load(Ordering::Relaxed);
+    info!(
+        "{} lines read, {} entities parsed, {} errors",
+        report.lines_read, report.entities_parsed, report.errors
+    );
+    let total = total.lock().unwrap().take().unwrap();
+    (total, report)
+}
+
+fn thread_pool(config: &Config) -> ThreadPool {
     let pool = ThreadPool::builder()
+        .pool_size(config.threads)
         .create()
         .expect("Create thread pool error");
+    info!(
+        "threads: {}, chunk size: {} lines, chunk bytes: {}",
+        config.threads,
+        config.chunk_size,
+        if config.chunk_bytes > 0 {
+            config.chunk_bytes.to_string()
+        } else {
+            String::from("unlimited")
+        }
+    );
+    pool
+}
 
-    let mut futures = vec![];
-    let input_file = &config.input_file;
-    let mut output_manager = OutputManager::new(&config.output_prefix);
-
+/// Read the lines of the dump and pass the entity lines to `spawn` in chunks of `chunk_size`
+/// lines or `chunk_bytes` bytes. Lines that don't pass `keep` are counted as skipped. Stops
+/// after `max_lines` entity lines or when `max_emitted` is reached. Returns the bytes read.
+fn read_chunks<K, F>(config: &Config, stats: &RunStats, keep: K, mut spawn: F) -> u64
+where
+    K: Fn(&str) -> bool,
+    F: FnMut(Vec<String>),
+{
     info!("open file...");
-    let file = File::open(input_file).expect("Input file open error");
-    //let buf = BzDecoder::new(file);
-    let buf = MultiGzDecoder::new(file);
+    let file = File::open(&config.input_file).expect("Input file open error");
+    let file_size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+    let bytes_read = Arc::new(AtomicU64::new(0));
+    let mut progress = Progress::new(file_size, bytes_read.clone(), config.progress_bar);
+    let buf = open_input(
+        CountingReader::new(file, bytes_read.clone()),
+        config.threads,
+    );
     let mut count = 0;
+    let mut entity_lines = 0;
     let mut buffer: Vec<String> = vec![];
+    let mut buffer_bytes = 0;
 
-    for line in BufReader::new(buf).lines() {
+    let mut lines = BufReader::new(buf).lines();
+    while let Some(line) = measure_stage(&config.metrics, |m| &m.read, || lines.next()) {
         match line {
             Ok(article) => {
-                if !skip_parse(&article, &config) {
+                if is_entity_line(&article) {
+                    entity_lines += 1;
+                }
+                if !is_entity_line(&article) || entity_lines <= config.skip {
+                    // neither parsed nor counted as skipped by the prefilter
+                } else if !keep(&article) {
+                    stats.lines_skipped.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    buffer_bytes += article.len();
                     buffer.push(article);
-                    if buffer.len() == config.chunk_size {
-                        futures.push(
-                            pool.spawn_with_handle(process_buffer(
-                                buffer,
-                                config.clone(),
-                                output_manager.create_output_json(),
-                            ))
-                            .expect("Spawn error..."),
-                        );
+                    if buffer.len() == config.chunk_size
+                        || (config.chunk_bytes > 0 && buffer_bytes >= config.chunk_bytes)
+                    {
+                        spawn(buffer);
                         buffer = vec![];
+                        buffer_bytes = 0;
                     }
                 }
             }
             Err(_) => {
                 warn!("Read line error. line[{}]", count);
+                stats.errors.fetch_add(1, Ordering::Relaxed);
             }
         }
         count += 1;
+        stats.lines_read.fetch_add(1, Ordering::Relaxed);
+        progress.tick(count);
         if count % 1_000_000 == 0 {
-            info!("{} docs operated...", count);
-        }
-        if config.with_limiter {
-            if count > config.limit {
-                info!("{} docs operated...", count);
-                break;
+            log_metrics(config);
+            if let Some(metrics_file) = &config.metrics_file {
+                stats.write_prometheus(metrics_file, bytes_read.load(Ordering::Relaxed));
             }
         }
+        if config.max_lines > 0 && entity_lines >= config.skip + config.max_lines {
+            break;
+        }
+        if stats.emitted_limit_reached(config.max_emitted) {
+            break;
+        }
     }
+    progress.finish(count);
+    log_metrics(config);
     debug!("Out the lines loop...");
     //TODO handle last docs in buffer
     if !buffer.is_empty() {
-        futures.push(
-            pool.spawn_with_handle(process_buffer(
-                buffer,
-                config.clone(),
-                output_manager.create_output_json(),
-            ))
-            .expect("Spawn error..."),
-        );
+        spawn(buffer);
     }
-    debug!("before block_on...");
-    block_on(futures::future::join_all(futures));
-    debug!("finish block_on...");
+    bytes_read.load(Ordering::Relaxed)
 }
 
-fn process_doc(doc: &mut Document, config: &Config) {
-    doc.copy_id();
+/// Returns false if the entity doesn't pass the text filters or the filter expression.
+fn process_doc(entity: &Entity, doc: &mut Document, config: &Config) -> bool {
+    let lang = config.lang.as_str();
+    if !config
+        .text_filters
+        .iter()
+        .all(|filter| filter.matches(entity, lang))
+    {
+        return false;
+    }
+    if let Some(filter) = &config.filter {
+        if !filter.matches(entity) {
+            return false;
+        }
+    }
+    let metrics = &config.metrics;
+    measure_stage(metrics, |m| &m.copy_id, || doc.copy_id(entity));
     // add label
-    doc.copy_labels(config);
+    measure_stage(
+        metrics,
+        |m| &m.copy_labels,
+        || doc.copy_labels(entity, config),
+    );
     // add description
-    doc.copy_desc(config);
+    measure_stage(metrics, |m| &m.copy_desc, || doc.copy_desc(entity, config));
     // add aliases
-    doc.copy_aliases(config);
+    measure_stage(
+        metrics,
+        |m| &m.copy_aliases,
+        || doc.copy_aliases(entity, config),
+    );
     // add claims
-    doc.copy_claims(config);
+    measure_stage(
+        metrics,
+        |m| &m.copy_claims,
+        || doc.copy_claims(entity, config),
+    );
+    // add user-defined fields
+    if !config.extractors.is_empty() {
+        measure_stage(
+            metrics,
+            |m| &m.extractors,
+            || {
+                for extractor in &config.extractors {
+                    extractor.extract(entity, &mut doc.new_map);
+                }
+            },
+        );
+    }
+    true
 }
 
 #[cfg(test)]
@@ -299,16 +1238,10 @@ mod tests {
     }
 
     fn dummy_config() -> Config {
-        return Config {
-            input_file: String::from(""),
-            output_prefix: String::from(""),
-            chunk_size: 100000,
-            properties: vec![String::from("P31")],
-            lang: String::from("ja"),
-            with_limiter: true,
-            limit: 0,
-            lang_regex: Regex::new(format!("\"{}\"", "ja").as_str()).unwrap(),
-        };
+        Config::builder()
+            .properties(&["P31"])
+            .language("ja")
+            .build()
     }
 
     #[test]
@@ -317,19 +1250,20 @@ mod tests {
         let article = json.as_str();
         let config = &dummy_config();
         let lang = format!("\"{}\"", config.lang);
+        let lang_regex = regex::Regex::new(lang.as_str()).unwrap();
         measure_ns!({
             for _i in 0..100 {
                 match article {
                     "[" => true,
                     "]" => true,
-                    _ => !config.lang_regex.is_match(article),
+                    _ => !lang_regex.is_match(article),
                 };
             }
         });
         measure_ns!({
             for _i in 0..100 {
                 if article.len() > 1 {
-                    !config.lang_regex.is_match(article)
+                    !lang_regex.is_match(article)
                 } else {
                     true
                 };
@@ -354,7 +1288,7 @@ mod tests {
 
         measure_ns!({
             for _i in 0..100 {
-                let hoge = config.lang_regex.is_match(article);
+                let _hoge = lang_regex.is_match(article);
             }
         });
 
@@ -370,45 +1304,140 @@ mod tests {
                 }
             }
         });
+
+        let config = &Config::builder()
+            .properties(&["P31", "P17"])
+            .require_properties(true)
+            .build();
+        let skipped = measure_ns!({
+            let mut skipped = true;
+            for _i in 0..100 {
+                skipped = skip_parse(article, config);
+            }
+            skipped
+        });
+        assert!(!skipped);
+    }
+
+    #[test]
+    fn check_parse_bytes() {
+        assert_eq!(parse_bytes("1024"), Some(1024));
+        assert_eq!(parse_bytes("64M"), Some(64 * 1024 * 1024));
+        assert_eq!(parse_bytes("2g"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_bytes("M"), None);
+        assert_eq!(parse_bytes("1.5G"), None);
+        assert_eq!(parse_bytes("99999999999G"), None);
+    }
+
+    #[test]
+    fn check_require_properties() {
+        let article = dummy_json();
+        let config = Config::builder()
+            .properties(&["P31", "P17"])
+            .require_properties(true)
+            .build();
+        assert!(!skip_parse(&article, &config));
+        assert!(parse_article(article.clone(), &config, &mut ChunkStats::default()).is_some());
+
+        // P999 never occurs
+        let config = Config::builder()
+            .properties(&["P31", "P999"])
+            .require_properties(true)
+            .build();
+        assert!(skip_parse(&article, &config));
+
+        // P248 occurs only in references, so the prefilter passes but the entity is dropped
+        let config = Config::builder()
+            .properties(&["P248"])
+            .require_properties(true)
+            .build();
+        assert!(!skip_parse(&article, &config));
+        assert!(parse_article(article.clone(), &config, &mut ChunkStats::default()).is_none());
+
+        let config = Config::builder().properties(&["P248"]).build();
+        assert!(parse_article(article, &config, &mut ChunkStats::default()).is_some());
+    }
+
+    #[test]
+    fn check_perf_parse() {
+        let article = dummy_json();
+        let config = &dummy_config();
+        // full map of every language and property
+        let map = measure_ns!({
+            let mut map = Map::new();
+            for _i in 0..100 {
+                map = serde_json::from_str(article.as_str())
+                    .expect("something wrong during parsing json");
+            }
+            map
+        });
+        // only the requested language and properties
+        let entity = measure_ns!({
+            let mut entity = None;
+            for _i in 0..100 {
+                entity = Some(
+                    Entity::parse(article.as_bytes(), config.entity_seed())
+                        .expect("something wrong during parsing json"),
+                );
+            }
+            entity.unwrap()
+        });
+        // both paths give the same values for what the config selects
+        assert_eq!(map["id"], Value::from(entity.id.as_ref()));
+        assert_eq!(
+            map["labels"]["ja"]["value"],
+            Value::from(entity.labels["ja"].as_ref())
+        );
+        assert_eq!(
+            map["descriptions"].get("ja").is_some(),
+            entity.descriptions.contains_key("ja")
+        );
+        assert_eq!(entity.claims.len(), 1);
+        let claims = map["claims"]["P31"].as_array().unwrap();
+        assert_eq!(claims.len(), entity.claims["P31"].len());
+        for (claim, statement) in claims.iter().zip(entity.claims["P31"].iter()) {
+            assert_eq!(
+                claim["mainsnak"]["datavalue"]["value"],
+                statement.mainsnak.datavalue.as_ref().unwrap().value
+            );
+        }
     }
 
     #[test]
     fn check_perf_process_doc() {
         let article = dummy_json();
         let config = &dummy_config();
-        let mut doc = Document {
-            original_map: serde_json::from_str(article.as_str())
-                .expect("something wrong during parsing json"),
-            new_map: Map::new(),
-        };
+        let entity = Entity::parse(article.as_bytes(), config.entity_seed())
+            .expect("something wrong during parsing json");
+        let mut doc = Document::new();
 
         measure_ns!({
             for _i in 0..100 {
-                doc.copy_id();
+                doc.copy_id(&entity);
             }
         });
         // add label
         measure_ns!({
             for _i in 0..100 {
-                doc.copy_labels(config);
+                doc.copy_labels(&entity, config);
             }
         });
         // add description
         measure_ns!({
             for _i in 0..100 {
-                doc.copy_desc(config);
+                doc.copy_desc(&entity, config);
             }
         });
         // add aliases
         measure_ns!({
             for _i in 0..100 {
-                doc.copy_aliases(config);
+                doc.copy_aliases(&entity, config);
             }
         });
         // add claims
         measure_ns!({
             for _i in 0..100 {
-                doc.copy_claims(config);
+                doc.copy_claims(&entity, config);
             }
         });
         assert_eq!(doc.new_map.len(), 3);
@@ -418,16 +1447,237 @@ mod tests {
     fn check_claims() {
         let article = dummy_json();
         let config = dummy_config();
-        let mut doc = Document {
-            original_map: serde_json::from_str(article.as_str())
-                .expect("something wrong during parsing json"),
-            new_map: Map::new(),
-        };
-        doc.copy_id();
-        doc.copy_claims(&config);
+        let entity = Entity::parse(article.as_bytes(), config.entity_seed())
+            .expect("something wrong during parsing json");
+        let mut doc = Document::new();
+        doc.copy_id(&entity);
+        doc.copy_claims(&entity, &config);
         assert_eq!(doc.new_map.len(), 2);
     }
 
+    #[test]
+    fn check_geo_filter() {
+        let scotland = GeoArea::bounding_box("-8,54.5,-0.7,60.9").unwrap();
+        let config = Config::builder()
+            .language("en")
+            .properties(&["P31"])
+            .geo_filter(GeoFilter::new("p625", scotland))
+            .build();
+        let mut stats = ChunkStats::default();
+        let doc = parse_article(dummy_json(), &config, &mut stats).unwrap();
+        let fields = doc.fields();
+        assert_eq!(fields["lat"].as_f64(), Some(57.302777777778));
+        assert_eq!(fields["lon"].as_f64(), Some(-6.3561111111111));
+        assert!(fields["claims"].get("P625").is_none());
+
+        let japan = GeoArea::radius("35.68,139.77,1000").unwrap();
+        let config = Config::builder()
+            .language("en")
+            .geo_filter(GeoFilter::new("P625", japan))
+            .build();
+        assert!(parse_article(dummy_json(), &config, &mut stats).is_none());
+    }
+
+    #[test]
+    fn check_time_range() {
+        let config = Config::builder()
+            .language("en")
+            .properties(&["P571"])
+            .time_range(TimeRange::parse("P571=1800..1850").unwrap())
+            .build();
+        let mut stats = ChunkStats::default();
+        let doc = parse_article(dummy_json(), &config, &mut stats).unwrap();
+        assert!(doc.fields().get("claims").is_none());
+
+        let config = Config::builder()
+            .language("en")
+            .properties(&["P571", "P31"])
+            .claim_dates(true)
+            .build();
+        let doc = parse_article(dummy_json(), &config, &mut stats).unwrap();
+        assert_eq!(doc.fields()["claims"]["P571"], serde_json::json!(["1830"]));
+        assert_eq!(
+            doc.fields()["claims"]["P31"],
+            serde_json::json!(["Q10373548"])
+        );
+
+        let config = Config::builder()
+            .language("en")
+            .time_range(TimeRange::parse("P571=1831..").unwrap())
+            .build();
+        assert!(parse_article(dummy_json(), &config, &mut stats).is_none());
+    }
+
+    #[test]
+    fn check_claim_quantities() {
+        let article = String::from(
+            r#"{"type":"item","id":"Q39231","labels":{"en":{"language":"en","value":"Mount Fuji"}},"claims":{"P2044":[{"mainsnak":{"snaktype":"value","property":"P2044","datavalue":{"value":{"amount":"+3776.24","unit":"http://www.wikidata.org/entity/Q11573"},"type":"quantity"},"datatype":"quantity"},"type":"statement","rank":"normal"}]}}"#,
+        );
+        let mut stats = ChunkStats::default();
+        let config = Config::builder()
+            .language("en")
+            .properties(&["P2044"])
+            .build();
+        let doc = parse_article(article.clone(), &config, &mut stats).unwrap();
+        assert!(doc.fields().get("claims").is_none());
+
+        let config = Config::builder()
+            .language("en")
+            .properties(&["P2044"])
+            .claim_quantities(true)
+            .build();
+        let doc = parse_article(article, &config, &mut stats).unwrap();
+        assert_eq!(
+            doc.fields()["claims"]["P2044"],
+            serde_json::json!([{"amount": 3776.24, "unit": "Q11573"}])
+        );
+    }
+
+    #[test]
+    fn check_filter_expr() {
+        let filter = FilterExpr::parse("P31=Q10373548 and (has(P625) or sitelink(frwiki))");
+        let config = Config::builder()
+            .language("en")
+            .filter(filter.unwrap())
+            .build();
+        assert_eq!(config.seed_properties, vec!["P31", "P625"]);
+        let mut stats = ChunkStats::default();
+        let doc = parse_article(dummy_json(), &config, &mut stats).unwrap();
+        assert!(doc.fields().get("claims").is_none());
+
+        let filter = FilterExpr::parse("P31=Q5 or not sitelink(jawiki)").unwrap();
+        let config = Config::builder().language("en").filter(filter).build();
+        assert!(parse_article(dummy_json(), &config, &mut stats).is_none());
+    }
+
+    #[test]
+    fn check_peek_id() {
+        assert_eq!(peek_id(&dummy_json()), Some("Q278"));
+        assert_eq!(
+            peek_id(r#"{"type":"property","datatype":"url","id": "P856"}"#),
+            Some("P856")
+        );
+        assert_eq!(peek_id("["), None);
+    }
+
+    #[test]
+    fn check_lazy_entity() {
+        let article = dummy_json();
+        let config = Config::builder().properties(&["P31", "P625"]).build();
+        let entity = Entity::parse(article.as_bytes(), config.entity_seed()).unwrap();
+        assert_eq!(entity.id, "Q278");
+        assert_eq!(entity.labels.len(), 1);
+        assert_eq!(entity.labels.get("ja").unwrap(), "タリスカー蒸留所");
+        assert!(entity.descriptions.is_empty());
+        let mut properties: Vec<&str> = entity.claims.keys().map(|x| x.as_ref()).collect();
+        properties.sort();
+        assert_eq!(properties, vec!["P31", "P625"]);
+        assert_eq!(entity.claims["P31"][0].value_id(), Some("Q10373548"));
+        assert_eq!(entity.sitelinks.len(), 19);
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn check_simd_json_backend() {
+        let config = Config::builder()
+            .properties(&["P31", "P17", "P131", "P625", "P571"])
+            .extractor(SitelinkCount)
+            .build();
+        let article = dummy_json();
+        let entity = Entity::parse(article.as_bytes(), config.entity_seed()).unwrap();
+        let mut serde_doc = Document::new();
+        process_doc(&entity, &mut serde_doc, &config);
+
+        let mut bytes = dummy_json().into_bytes();
+        let entity = Entity::parse_simd(&mut bytes, config.entity_seed()).unwrap();
+        let mut simd_doc = Document::new();
+        process_doc(&entity, &mut simd_doc, &config);
+
+        assert_eq!(serde_doc.to_json_string(), simd_doc.to_json_string());
+        assert_eq!(
+            parse_article(dummy_json(), &config, &mut ChunkStats::default())
+                .unwrap()
+                .to_json_string(),
+            serde_doc.to_json_string()
+        );
+    }
+
+    #[cfg(feature = "simd-json")]
+    #[test]
+    fn check_simd_json_trailing_input() {
+        let languages = vec![String::from("ja")];
+        for line in &[
+            r#"{"type":"item","id":"Q1"} x"#,
+            r#"{"type":"item","id":"Q1"}{}"#,
+            r#"{"type":"item","id":"Q1"},"#,
+        ] {
+            let seed = EntitySeed::new(&languages, None);
+            assert!(Entity::parse(line.as_bytes(), seed).is_err(), "{}", line);
+            let mut bytes = line.as_bytes().to_vec();
+            assert!(Entity::parse_simd(&mut bytes, seed).is_err(), "{}", line);
+        }
+        let mut bytes = br#"{"type":"item","id":"Q1"}  "#.to_vec();
+        let seed = EntitySeed::new(&languages, None);
+        assert_eq!(Entity::parse_simd(&mut bytes, seed).unwrap().id, "Q1");
+    }
+
+    #[derive(Debug)]
+    struct SitelinkCount;
+
+    impl Extractor for SitelinkCount {
+        fn extract(&self, entity: &Entity, output: &mut Map<String, Value>) {
+            output.insert(
+                String::from("sitelinks"),
+                Value::from(entity.sitelinks.len()),
+            );
+        }
+    }
+
+    #[test]
+    fn check_extractors() {
+        let config = Config::builder()
+            .properties(&["P31"])
+            .extractor(SitelinkCount)
+            .build();
+        let doc = parse_article(dummy_json(), &config, &mut ChunkStats::default()).unwrap();
+        assert_eq!(doc.fields().get("sitelinks"), Some(&Value::from(19)));
+        assert_eq!(doc.fields().len(), 4);
+    }
+
+    /// Reads P571, which is not in the properties of the config.
+    #[derive(Debug)]
+    struct Inception;
+
+    impl Extractor for Inception {
+        fn extract(&self, entity: &Entity, output: &mut Map<String, Value>) {
+            if let Some(statement) = entity.claims.get("P571").and_then(|x| x.first()) {
+                if let Some(datavalue) = &statement.mainsnak.datavalue {
+                    output.insert(String::from("inception"), datavalue.value["time"].clone());
+                }
+            }
+        }
+
+        fn properties(&self) -> Vec<String> {
+            vec![String::from("P571")]
+        }
+    }
+
+    #[test]
+    fn check_extractor_properties() {
+        let config = Config::builder()
+            .properties(&["P31"])
+            .extractor(Inception)
+            .build();
+        assert_eq!(config.seed_properties, vec!["P31", "P571"]);
+        let doc = parse_article(dummy_json(), &config, &mut ChunkStats::default()).unwrap();
+        assert_eq!(
+            doc.fields().get("inception"),
+            Some(&Value::from("+1830-00-00T00:00:00Z"))
+        );
+        // read by the extractor, not copied to the claims
+        assert_eq!(doc.fields()["claims"].as_object().unwrap().len(), 1);
+    }
+
     #[test]
     fn check_speed_check_last_char() {
         let mut article = dummy_json();
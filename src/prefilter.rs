@@ -0,0 +1,127 @@
+use aho_corasick::AhoCorasick;
+use std::fmt;
+
+/// Byte-level check on a raw line, before the line is parsed as JSON.
+/// A line passes only if every pattern occurs somewhere in it, so a pass can still be a false
+/// positive (e.g. a property key in references), but a reject is always right.
+#[derive(Clone)]
+pub struct Prefilter {
+    matcher: AhoCorasick,
+    patterns: Vec<String>,
+    /// One bit per pattern, when there are at most 64 patterns.
+    all: Option<u64>,
+}
+
+impl fmt::Debug for Prefilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Prefilter").field(&self.patterns).finish()
+    }
+}
+
+impl Prefilter {
+    pub fn new(patterns: &[String]) -> Self {
+        Prefilter {
+            matcher: AhoCorasick::new(patterns),
+            patterns: patterns.to_vec(),
+            all: match patterns.len() {
+                0 => Some(0),
+                n if n <= 64 => Some(u64::MAX >> (64 - n)),
+                _ => None,
+            },
+        }
+    }
+
+    /// `"ja"` for a language, it matches the key of labels, descriptions and aliases.
+    pub fn lang_pattern(lang: &str) -> String {
+        format!("\"{}\"", lang)
+    }
+
+    /// `"P31":[` for a property, it matches the key of claims.
+    pub fn property_pattern(property: &str) -> String {
+        format!("\"{}\":[", property)
+    }
+
+    pub fn is_match(&self, article: &str) -> bool {
+        let all = match self.all {
+            Some(all) => all,
+            None => return self.is_match_many(article),
+        };
+        if all == 0 {
+            return true;
+        }
+        let mut found = 0u64;
+        for mat in self.matcher.find_overlapping_iter(article) {
+            found |= 1 << mat.pattern();
+            if found == all {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// `is_match` for more than 64 patterns, tracked in a heap allocated list.
+    fn is_match_many(&self, article: &str) -> bool {
+        let mut found = vec![false; self.patterns.len()];
+        let mut remaining = self.patterns.len();
+        for mat in self.matcher.find_overlapping_iter(article) {
+            if !found[mat.pattern()] {
+                found[mat.pattern()] = true;
+                remaining -= 1;
+                if remaining == 0 {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Passes if any pattern occurs, e.g. for entities with one of several properties.
+    pub fn is_any_match(&self, article: &str) -> bool {
+        self.matcher.is_match(article)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prefilter::*;
+
+    #[test]
+    fn check_is_match() {
+        let article = r#"{"id":"Q1","labels":{"ja":{"language":"ja","value":"x"}},"claims":{"P31":[],"P17":[]}}"#;
+        let patterns = vec![
+            Prefilter::lang_pattern("ja"),
+            Prefilter::property_pattern("P31"),
+            Prefilter::property_pattern("P17"),
+        ];
+        assert!(Prefilter::new(&patterns).is_match(article));
+        assert!(!Prefilter::new(&[Prefilter::lang_pattern("en")]).is_match(article));
+        assert!(!Prefilter::new(&[Prefilter::property_pattern("P3")]).is_match(article));
+        assert!(!Prefilter::new(&[Prefilter::property_pattern("P1")]).is_match("["));
+        assert!(Prefilter::new(&[]).is_match("["));
+
+        let any = Prefilter::new(&[
+            Prefilter::property_pattern("P3"),
+            Prefilter::property_pattern("P17"),
+        ]);
+        assert!(!any.is_match(article));
+        assert!(any.is_any_match(article));
+        assert!(!Prefilter::new(&[Prefilter::property_pattern("P3")]).is_any_match(article));
+    }
+
+    #[test]
+    fn check_is_match_many_patterns() {
+        let properties: Vec<String> = (1..=70).map(|i| format!("P{}", i)).collect();
+        let claims: Vec<String> = properties.iter().map(|p| format!("\"{}\":[]", p)).collect();
+        let article = format!(r#"{{"claims":{{{}}}}}"#, claims.join(","));
+        let patterns: Vec<String> = properties
+            .iter()
+            .map(|p| Prefilter::property_pattern(p))
+            .collect();
+        assert!(Prefilter::new(&patterns[..64]).is_match(&article));
+        assert!(Prefilter::new(&patterns).is_match(&article));
+        let mut missing = patterns.clone();
+        missing.push(Prefilter::property_pattern("P71"));
+        assert!(!Prefilter::new(&missing).is_match(&article));
+        assert!(!Prefilter::new(&missing[6..]).is_match(&article));
+    }
+}
@@ -0,0 +1,106 @@
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// Settings of one named profile in a `--config` TOML file.
+/// Every field is optional, a missing field falls back to the CLI default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub input_file: Option<String>,
+    pub output_prefix: Option<String>,
+    pub properties: Option<Vec<String>>,
+    pub language: Option<String>,
+    /// `limit` is accepted too.
+    #[serde(alias = "limit")]
+    pub max_lines: Option<u64>,
+    pub max_emitted: Option<u64>,
+    pub skip: Option<u64>,
+    pub sample_rate: Option<f64>,
+    pub sample_size: Option<u64>,
+    pub sample_seed: Option<u64>,
+    pub include_ids: Option<String>,
+    pub exclude_ids: Option<String>,
+    /// `"WEST,SOUTH,EAST,NORTH"`
+    pub geo_bbox: Option<String>,
+    /// `"LAT,LON,KM"`
+    pub geo_radius: Option<String>,
+    /// Path of a GeoJSON file.
+    pub geo_polygon: Option<String>,
+    pub geo_property: Option<String>,
+    /// e.g. `["P569=1900..1950"]`
+    pub time_ranges: Option<Vec<String>>,
+    pub claim_dates: Option<bool>,
+    pub claim_quantities: Option<bool>,
+    pub unit_table: Option<String>,
+    /// e.g. `["labels:script:Han"]`
+    pub text_filters: Option<Vec<String>>,
+    /// e.g. `"(P31=Q5 and has(P569)) or sitelink(jawiki)"`
+    pub filter: Option<String>,
+    pub threads: Option<usize>,
+    pub chunk_size: Option<usize>,
+    /// e.g. `"64M"`
+    pub chunk_bytes: Option<String>,
+    pub require_properties: Option<bool>,
+    pub stats_file: Option<String>,
+    pub metrics_file: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+impl Profile {
+    /// Load the profile named `name` from the TOML file at `path`.
+    pub fn load(path: &str, name: &str) -> Self {
+        let content =
+            fs::read_to_string(path).unwrap_or_else(|_| panic!("can't read config file[{}]", path));
+        Profile::from_toml(&content, name)
+            .unwrap_or_else(|_| panic!("Error in config file[{}]", path))
+    }
+
+    fn from_toml(content: &str, name: &str) -> Result<Self, String> {
+        let mut file: ConfigFile = toml::from_str(content).map_err(|e| e.to_string())?;
+        match file.profiles.remove(name) {
+            Some(profile) => Ok(profile),
+            None => Err(format!("profile[{}] is not defined", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::profile::*;
+
+    const CONFIG: &str = r#"
+[profiles.search-ja]
+input_file = "latest-all.json.gz"
+output_prefix = "out/search_ja"
+language = "ja"
+properties = ["P31", "P279"]
+chunk_size = 50000
+
+[profiles.geo]
+properties = ["P625"]
+"#;
+
+    #[test]
+    fn check_load_profile() {
+        let profile = Profile::from_toml(CONFIG, "search-ja").unwrap();
+        assert_eq!(profile.language, Some(String::from("ja")));
+        assert_eq!(profile.chunk_size, Some(50000));
+        assert_eq!(profile.max_lines, None);
+        let geo = Profile::from_toml(CONFIG, "geo").unwrap();
+        assert_eq!(geo.properties, Some(vec![String::from("P625")]));
+        assert!(geo.input_file.is_none());
+    }
+
+    #[test]
+    fn check_unknown_profile_and_key() {
+        assert!(Profile::from_toml(CONFIG, "unknown").is_err());
+        assert!(Profile::from_toml("[profiles.a]\nlang = \"ja\"", "a").is_err());
+    }
+}
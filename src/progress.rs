@@ -0,0 +1,151 @@
+use log::info;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Counts the bytes read from the input file, i.e. the compressed bytes.
+pub struct CountingReader<R: Read> {
+    inner: R,
+    bytes: Arc<AtomicU64>,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, bytes: Arc<AtomicU64>) -> Self {
+        CountingReader { inner, bytes }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+/// Reports the progress of reading the input file: percentage of the file size, lines/sec,
+/// MB/s of the input and ETA.
+pub struct Progress {
+    start: Instant,
+    total_bytes: u64,
+    bytes: Arc<AtomicU64>,
+    bar: bool,
+    last_draw: Instant,
+}
+
+/// Redraw interval of the progress bar.
+const DRAW_INTERVAL: Duration = Duration::from_millis(200);
+
+impl Progress {
+    /// `bar` renders a progress bar on stderr, only if stderr is a terminal.
+    pub fn new(total_bytes: u64, bytes: Arc<AtomicU64>, bar: bool) -> Self {
+        let now = Instant::now();
+        Progress {
+            start: now,
+            total_bytes,
+            bytes,
+            bar: bar && io::stderr().is_terminal(),
+            last_draw: now,
+        }
+    }
+
+    /// Called by the reader for each line, cheap unless there is something to report.
+    pub fn tick(&mut self, lines: u64) {
+        if lines.is_multiple_of(1_000_000) {
+            self.clear_bar();
+            info!("{}", self.status(lines));
+        }
+        if self.bar && lines.is_multiple_of(10_000) && self.last_draw.elapsed() >= DRAW_INTERVAL {
+            self.draw_bar(lines);
+        }
+    }
+
+    pub fn finish(&mut self, lines: u64) {
+        self.clear_bar();
+        info!("{}", self.status(lines));
+    }
+
+    fn status(&self, lines: u64) -> String {
+        let elapsed = self.start.elapsed().as_secs_f64().max(0.001);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let mut status = format!(
+            "{} docs operated... {:.0} docs/s, {:.1} MB/s",
+            lines,
+            lines as f64 / elapsed,
+            bytes as f64 / elapsed / 1_000_000.0
+        );
+        if let Some(ratio) = self.ratio(bytes) {
+            status.push_str(&format!(", {:.1}%", ratio * 100.0));
+            if let Some(eta) = eta(elapsed, ratio) {
+                status.push_str(&format!(", ETA {}", format_duration(eta)));
+            }
+        }
+        status
+    }
+
+    fn ratio(&self, bytes: u64) -> Option<f64> {
+        if self.total_bytes > 0 {
+            Some((bytes as f64 / self.total_bytes as f64).min(1.0))
+        } else {
+            None
+        }
+    }
+
+    fn draw_bar(&mut self, lines: u64) {
+        const WIDTH: usize = 30;
+        self.last_draw = Instant::now();
+        let ratio = self
+            .ratio(self.bytes.load(Ordering::Relaxed))
+            .unwrap_or(0.0);
+        let filled = (ratio * WIDTH as f64) as usize;
+        let _ = write!(
+            io::stderr(),
+            "\r\x1b[K[{}{}] {}",
+            "#".repeat(filled),
+            ".".repeat(WIDTH - filled),
+            self.status(lines)
+        );
+    }
+
+    fn clear_bar(&self) {
+        if self.bar {
+            let _ = write!(io::stderr(), "\r\x1b[K");
+        }
+    }
+}
+
+/// Remaining seconds when `ratio` of the input took `elapsed` seconds.
+fn eta(elapsed: f64, ratio: f64) -> Option<u64> {
+    if ratio > 0.0 {
+        Some((elapsed * (1.0 - ratio) / ratio) as u64)
+    } else {
+        None
+    }
+}
+
+fn format_duration(secs: u64) -> String {
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::*;
+
+    #[test]
+    fn check_eta() {
+        assert_eq!(eta(100.0, 0.25), Some(300));
+        assert_eq!(eta(100.0, 1.0), Some(0));
+        assert_eq!(eta(100.0, 0.0), None);
+        assert_eq!(format_duration(3 * 3600 + 25 * 60 + 7), "03:25:07");
+    }
+
+    #[test]
+    fn check_counting_reader() {
+        let bytes = Arc::new(AtomicU64::new(0));
+        let mut reader = CountingReader::new("abcdef".as_bytes(), bytes.clone());
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert_eq!(bytes.load(Ordering::Relaxed), 6);
+    }
+}
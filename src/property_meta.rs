@@ -0,0 +1,112 @@
+use crate::entity::{peek_id, Entity, Statement};
+use crate::parser::{scan_entities, Config};
+use log::info;
+use serde_derive::Serialize;
+use std::collections::BTreeMap;
+
+/// Formatter URL, e.g. `https://isni.org/isni/$1`.
+const FORMATTER_URL: &str = "P1630";
+/// Property constraint, e.g. Q21502410 (distinct-values constraint).
+const CONSTRAINT: &str = "P2302";
+
+/// Metadata of a property entity, to render output with property names.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PropertyMeta {
+    /// Language code to label, only the requested languages.
+    pub labels: BTreeMap<String, String>,
+    pub datatype: String,
+    /// Preferred ones first, deprecated ones are skipped.
+    pub formatter_urls: Vec<String>,
+    /// Q-ids of the constraint types.
+    pub constraints: Vec<String>,
+}
+
+/// Preferred statements first, without the deprecated ones.
+fn ranked<'s, 'a>(entity: &'s Entity<'a>, property: &str) -> Vec<&'s Statement<'a>> {
+    let mut statements: Vec<&Statement> = entity
+        .claims
+        .get(property)
+        .into_iter()
+        .flatten()
+        .filter(|statement| statement.rank != "deprecated")
+        .collect();
+    statements.sort_by_key(|statement| statement.rank != "preferred");
+    statements
+}
+
+impl PropertyMeta {
+    /// `None` if the entity is not a property.
+    pub fn from_entity(entity: &Entity) -> Option<Self> {
+        if entity.entity_type != "property" {
+            return None;
+        }
+        let formatter_urls = ranked(entity, FORMATTER_URL)
+            .into_iter()
+            .filter_map(|statement| statement.mainsnak.datavalue.as_ref())
+            .filter_map(|datavalue| datavalue.value.as_str())
+            .map(String::from)
+            .collect();
+        let constraints = ranked(entity, CONSTRAINT)
+            .into_iter()
+            .filter_map(|statement| statement.value_id())
+            .map(String::from)
+            .collect();
+        Some(PropertyMeta {
+            labels: entity
+                .labels
+                .iter()
+                .map(|(lang, label)| (lang.to_string(), label.to_string()))
+                .collect(),
+            datatype: entity.datatype.to_string(),
+            formatter_urls,
+            constraints,
+        })
+    }
+}
+
+/// Parse the property entities of the dump and collect their metadata by id, with labels in
+/// `languages`. Items are skipped by their id before JSON parsing.
+pub fn scan_properties(config: &Config, languages: &[String]) -> BTreeMap<String, PropertyMeta> {
+    let (dictionary, _) = scan_entities(
+        config,
+        Some(languages.to_vec()),
+        Some(vec![FORMATTER_URL.to_string(), CONSTRAINT.to_string()]),
+        |article| peek_id(article).is_some_and(|id| id.starts_with('P')),
+        BTreeMap::new,
+        |entity, dictionary: &mut BTreeMap<String, PropertyMeta>| {
+            if let Some(meta) = PropertyMeta::from_entity(entity) {
+                dictionary.insert(entity.id.to_string(), meta);
+            }
+        },
+        |dictionary, chunk| dictionary.extend(chunk),
+    );
+    info!("{} properties", dictionary.len());
+    dictionary
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::EntitySeed;
+    use crate::property_meta::*;
+
+    const ISNI: &str = r#"{"type":"property","datatype":"external-id","id":"P213","labels":{"en":{"language":"en","value":"ISNI"},"ja":{"language":"ja","value":"ISNI"},"fr":{"language":"fr","value":"ISNI"}},"descriptions":{},"aliases":{},"claims":{"P1630":[{"mainsnak":{"snaktype":"value","property":"P1630","datavalue":{"value":"http://isni.org/$1","type":"string"},"datatype":"string"},"rank":"deprecated"},{"mainsnak":{"snaktype":"value","property":"P1630","datavalue":{"value":"https://isni.org/isni/$1","type":"string"},"datatype":"string"},"rank":"normal"},{"mainsnak":{"snaktype":"value","property":"P1630","datavalue":{"value":"https://isni.oclc.org/xslt/DB=1.2//CMD?ACT=SRCH&IKT=8006&TRM=ISN%3A$1","type":"string"},"datatype":"string"},"rank":"preferred"}],"P2302":[{"mainsnak":{"snaktype":"value","property":"P2302","datavalue":{"value":{"entity-type":"item","numeric-id":21502404,"id":"Q21502404"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"},{"mainsnak":{"snaktype":"value","property":"P2302","datavalue":{"value":{"entity-type":"item","numeric-id":19474404,"id":"Q19474404"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"rank":"normal"}]}}"#;
+    const ITEM: &str = r#"{"type":"item","id":"Q1","labels":{"en":{"language":"en","value":"Universe"}},"claims":{}}"#;
+
+    #[test]
+    fn check_from_entity() {
+        let languages = vec![String::from("en"), String::from("ja")];
+        let seed = EntitySeed::new(&languages, None);
+        let entity = Entity::parse(ISNI.as_bytes(), seed).unwrap();
+        let meta = PropertyMeta::from_entity(&entity).unwrap();
+        assert_eq!(meta.datatype, "external-id");
+        assert_eq!(meta.labels.keys().collect::<Vec<_>>(), vec!["en", "ja"]);
+        assert_eq!(meta.formatter_urls.len(), 2);
+        assert!(meta.formatter_urls[0].starts_with("https://isni.oclc.org/"));
+        assert_eq!(meta.formatter_urls[1], "https://isni.org/isni/$1");
+        assert_eq!(meta.constraints, vec!["Q21502404", "Q19474404"]);
+
+        let entity = Entity::parse(ITEM.as_bytes(), seed).unwrap();
+        assert!(entity.datatype.is_empty());
+        assert!(PropertyMeta::from_entity(&entity).is_none());
+    }
+}
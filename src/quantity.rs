@@ -0,0 +1,191 @@
+use crate::entity::Statement;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+const ENTITY_PREFIX: &str = "http://www.wikidata.org/entity/";
+
+/// A quantity value of Wikidata with numeric amount and bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub amount: f64,
+    /// Q-id of the unit, `None` for a plain number.
+    pub unit: Option<String>,
+    pub lower: Option<f64>,
+    pub upper: Option<f64>,
+}
+
+/// Amounts are strings like `"+1234.5"` in dumps.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::String(number) => number.trim_start_matches('+').parse::<f64>().ok(),
+        Value::Number(number) => number.as_f64(),
+        _ => None,
+    }
+}
+
+impl Quantity {
+    /// Parse the value of a `quantity` datavalue, e.g.
+    /// `{"amount":"+1234","unit":"http://www.wikidata.org/entity/Q11573","lowerBound":"+1233"}`.
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let unit = match value["unit"].as_str() {
+            Some("1") | None => None,
+            Some(unit) => Some(unit.trim_start_matches(ENTITY_PREFIX).to_string()),
+        };
+        Some(Quantity {
+            amount: number(&value["amount"])?,
+            unit,
+            lower: number(&value["lowerBound"]),
+            upper: number(&value["upperBound"]),
+        })
+    }
+
+    pub fn from_statement(statement: &Statement) -> Option<Self> {
+        let datavalue = statement.mainsnak.datavalue.as_ref()?;
+        if datavalue.value_type != "quantity" {
+            return None;
+        }
+        Quantity::from_value(&datavalue.value)
+    }
+
+    /// `{"amount":1234.0,"unit":"Q11573","lower":1233.0,"upper":1235.0}`, without the missing
+    /// unit and bounds.
+    pub fn to_json(&self) -> Value {
+        let mut value = json!({ "amount": self.amount });
+        if let Some(unit) = &self.unit {
+            value["unit"] = Value::from(unit.as_str());
+        }
+        if let Some(lower) = self.lower {
+            value["lower"] = Value::from(lower);
+        }
+        if let Some(upper) = self.upper {
+            value["upper"] = Value::from(upper);
+        }
+        value
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Conversion {
+    unit: String,
+    factor: f64,
+    offset: f64,
+}
+
+/// Converts quantities to canonical units, e.g. centimetres and feet to metres.
+#[derive(Debug, Clone, Default)]
+pub struct UnitTable {
+    conversions: HashMap<String, Conversion>,
+}
+
+impl UnitTable {
+    /// `FROM_UNIT TO_UNIT FACTOR [OFFSET]` lines with the columns separated by tabs, converted as
+    /// `amount * FACTOR + OFFSET`. Empty lines and `#` comments are skipped.
+    ///
+    /// ```text
+    /// # centimetre -> metre
+    /// Q174728 Q11573  0.01
+    /// # degree Celsius -> kelvin
+    /// Q25267  Q11579  1   273.15
+    /// ```
+    pub fn from_tsv(tsv: &str) -> Result<Self, String> {
+        let mut conversions = HashMap::new();
+        for (number, line) in tsv.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || {
+                format!(
+                    "line {}: FROM_UNIT TO_UNIT FACTOR [OFFSET] expected",
+                    number + 1
+                )
+            };
+            let columns: Vec<&str> = line.split('\t').map(|x| x.trim()).collect();
+            if columns.len() < 3 || columns.len() > 4 {
+                return Err(invalid());
+            }
+            let factor = columns[2].parse::<f64>().map_err(|_| invalid())?;
+            let offset = match columns.get(3) {
+                Some(offset) => offset.parse::<f64>().map_err(|_| invalid())?,
+                None => 0.0,
+            };
+            conversions.insert(
+                columns[0].trim_start_matches(ENTITY_PREFIX).to_string(),
+                Conversion {
+                    unit: columns[1].trim_start_matches(ENTITY_PREFIX).to_string(),
+                    factor,
+                    offset,
+                },
+            );
+        }
+        Ok(UnitTable { conversions })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let tsv = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        UnitTable::from_tsv(&tsv).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    /// Quantities in units that are not in the table are returned as is.
+    pub fn convert(&self, quantity: Quantity) -> Quantity {
+        let conversion = match quantity
+            .unit
+            .as_ref()
+            .and_then(|unit| self.conversions.get(unit))
+        {
+            Some(conversion) => conversion,
+            None => return quantity,
+        };
+        let convert = |x: f64| x * conversion.factor + conversion.offset;
+        Quantity {
+            amount: convert(quantity.amount),
+            unit: Some(conversion.unit.clone()),
+            lower: quantity.lower.map(convert),
+            upper: quantity.upper.map(convert),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quantity::*;
+
+    #[test]
+    fn check_quantity() {
+        let population = Quantity::from_value(&json!({"amount": "+13960000", "unit": "1"}));
+        let population = population.unwrap();
+        assert_eq!(population.to_json(), json!({"amount": 13960000.0}));
+        let height = Quantity::from_value(&json!({
+            "amount": "+3776.24",
+            "unit": "http://www.wikidata.org/entity/Q11573",
+            "lowerBound": "+3776.23",
+            "upperBound": "+3776.25"
+        }))
+        .unwrap();
+        assert_eq!(height.unit.as_deref(), Some("Q11573"));
+        assert_eq!(height.lower, Some(3776.23));
+        assert!(Quantity::from_value(&json!({"amount": "many"})).is_none());
+    }
+
+    #[test]
+    fn check_unit_table() {
+        let tsv = "# cm -> m\nQ174728\tQ11573\t0.01\n\nQ25267\tQ11579\t1\t273.15\n";
+        let table = UnitTable::from_tsv(tsv).unwrap();
+        let cm = Quantity {
+            amount: 150.0,
+            unit: Some(String::from("Q174728")),
+            lower: Some(149.0),
+            upper: None,
+        };
+        let m = table.convert(cm);
+        assert!((m.amount - 1.5).abs() < 1e-9);
+        assert!((m.lower.unwrap() - 1.49).abs() < 1e-9);
+        assert_eq!(m.unit.as_deref(), Some("Q11573"));
+        let celsius = Quantity::from_value(&json!({"amount": "+20", "unit": "Q25267"})).unwrap();
+        assert!((table.convert(celsius).amount - 293.15).abs() < 1e-9);
+        let plain = Quantity::from_value(&json!({"amount": "+5", "unit": "1"})).unwrap();
+        assert_eq!(table.convert(plain.clone()), plain);
+        assert!(UnitTable::from_tsv("Q1\tQ2").is_err());
+        assert!(UnitTable::from_tsv("Q1\tQ2\tx").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::parser::Document;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Stable 64 bit hash of an entity id, the same on every platform, run and dump version.
+/// FNV-1a over the seed and the id, then the splitmix64 finalizer to spread the bits.
+pub fn id_hash(seed: u64, id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in seed.to_le_bytes().iter().chain(id.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// True for a `rate` fraction of the hashes, e.g. 0.01 keeps 1%.
+pub fn keep_by_rate(hash: u64, rate: f64) -> bool {
+    rate >= 1.0 || (hash as f64) < rate * (u64::MAX as f64)
+}
+
+struct Sampled {
+    hash: u64,
+    doc: Document,
+}
+
+impl PartialEq for Sampled {
+    fn eq(&self, other: &Self) -> bool {
+        self.hash == other.hash
+    }
+}
+
+impl Eq for Sampled {}
+
+impl PartialOrd for Sampled {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sampled {
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        self.hash.cmp(&other.hash)
+    }
+}
+
+/// Reservoir of the `size` documents with the smallest id hashes.
+///
+/// The hash plays the role of the random key of reservoir sampling, so the sample is uniform,
+/// independent of the order in which the workers offer documents, and reproducible with the
+/// same seed.
+pub struct Reservoir {
+    size: usize,
+    heap: Mutex<BinaryHeap<Sampled>>,
+    /// The largest hash in a full reservoir, `u64::MAX` until it is full.
+    threshold: AtomicU64,
+}
+
+impl Reservoir {
+    pub fn new(size: usize) -> Self {
+        Reservoir {
+            size,
+            heap: Mutex::new(BinaryHeap::with_capacity(size + 1)),
+            threshold: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// False if a document with this hash can't make it, checked before parsing.
+    pub fn may_keep(&self, hash: u64) -> bool {
+        hash < self.threshold.load(Ordering::Relaxed)
+    }
+
+    pub fn offer(&self, hash: u64, doc: Document) {
+        if self.size == 0 || !self.may_keep(hash) {
+            return;
+        }
+        let mut heap = self.heap.lock().unwrap();
+        heap.push(Sampled { hash, doc });
+        if heap.len() > self.size {
+            heap.pop();
+        }
+        if heap.len() == self.size {
+            let max = heap.peek().map(|sampled| sampled.hash).unwrap_or(u64::MAX);
+            self.threshold.store(max, Ordering::Relaxed);
+        }
+    }
+
+    /// The sample, in the order of the hashes.
+    pub fn take_documents(&self) -> Vec<Document> {
+        let heap = std::mem::take(&mut *self.heap.lock().unwrap());
+        heap.into_sorted_vec()
+            .into_iter()
+            .map(|sampled| sampled.doc)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::Entity;
+    use crate::parser::Document;
+    use crate::sample::*;
+
+    fn doc(id: &str) -> Document {
+        let mut doc = Document::new();
+        doc.copy_id(&Entity {
+            id: id.into(),
+            ..Entity::default()
+        });
+        doc
+    }
+
+    #[test]
+    fn check_id_hash() {
+        assert_eq!(id_hash(0, "Q42"), id_hash(0, "Q42"));
+        assert_ne!(id_hash(0, "Q42"), id_hash(1, "Q42"));
+        assert_ne!(id_hash(0, "Q42"), id_hash(0, "Q43"));
+        let kept = (0..100_000)
+            .filter(|i| keep_by_rate(id_hash(7, &format!("Q{}", i)), 0.01))
+            .count();
+        assert!(kept > 800 && kept < 1200, "kept {}", kept);
+        assert!(!keep_by_rate(0, 0.0));
+        assert!(keep_by_rate(u64::MAX - 1, 1.0));
+    }
+
+    #[test]
+    fn check_reservoir() {
+        let ids: Vec<String> = (0..1000).map(|i| format!("Q{}", i)).collect();
+        let mut expected: Vec<(u64, &str)> =
+            ids.iter().map(|id| (id_hash(3, id), id.as_str())).collect();
+        expected.sort();
+        let expected: Vec<&str> = expected.iter().take(10).map(|x| x.1).collect();
+
+        let reservoir = Reservoir::new(10);
+        for id in ids.iter().rev() {
+            reservoir.offer(id_hash(3, id), doc(id));
+        }
+        let sample = reservoir.take_documents();
+        let sample: Vec<&str> = sample.iter().filter_map(|doc| doc.id()).collect();
+        assert_eq!(sample, expected);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::entity::Entity;
+use crate::parser::{scan_entities, Config};
+use log::info;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+/// The shape of the values of one property, from the main snaks of its statements.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PropertySchema {
+    pub statements: u64,
+    /// `datatype` of the snaks, e.g. `external-id`, to the number of statements.
+    pub datatypes: BTreeMap<String, u64>,
+    /// `type` of the datavalues, e.g. `string`, to the number of statements.
+    pub value_types: BTreeMap<String, u64>,
+    /// `snaktype`, `value`, `somevalue` or `novalue`, to the number of statements.
+    pub snaktypes: BTreeMap<String, u64>,
+    /// Distinct datavalues of the entities with the lowest ids, so the examples don't depend on
+    /// the order the chunks are merged in.
+    pub examples: Vec<Value>,
+    /// Candidate examples with the id of the entity they come from, sorted by id.
+    #[serde(skip)]
+    candidates: Vec<(IdKey, Value)>,
+}
+
+/// Orders entity ids by number, `Q9` before `Q10`.
+type IdKey = (u64, String);
+
+fn id_key(id: &str) -> IdKey {
+    let number = id.get(1..).and_then(|n| n.parse().ok()).unwrap_or(u64::MAX);
+    (number, id.to_string())
+}
+
+impl PropertySchema {
+    fn add_example(&mut self, id: &IdKey, value: &Value, max_examples: usize) {
+        match self.candidates.iter().position(|(_, v)| v == value) {
+            Some(i) if self.candidates[i].0 <= *id => return,
+            Some(i) => self.candidates[i].0 = id.clone(),
+            None => self.candidates.push((id.clone(), value.clone())),
+        }
+        self.candidates.sort_by(|a, b| a.0.cmp(&b.0));
+        self.candidates.truncate(max_examples);
+    }
+}
+
+fn add_counts(total: &mut BTreeMap<String, u64>, counts: BTreeMap<String, u64>) {
+    for (key, count) in counts {
+        *total.entry(key).or_insert(0) += count;
+    }
+}
+
+/// Property catalogue of a dump, built by the `schema` subcommand.
+#[derive(Debug, Default)]
+pub struct Schema {
+    max_examples: usize,
+    properties: HashMap<String, PropertySchema>,
+}
+
+impl Schema {
+    /// Keep up to `max_examples` example values per property.
+    pub fn new(max_examples: usize) -> Self {
+        Schema {
+            max_examples,
+            properties: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, entity: &Entity) {
+        let id = id_key(&entity.id);
+        for (property, statements) in &entity.claims {
+            let schema = self.properties.entry(property.to_string()).or_default();
+            for statement in statements {
+                let snak = &statement.mainsnak;
+                schema.statements += 1;
+                *schema
+                    .datatypes
+                    .entry(snak.datatype.to_string())
+                    .or_insert(0) += 1;
+                *schema
+                    .snaktypes
+                    .entry(snak.snaktype.to_string())
+                    .or_insert(0) += 1;
+                if let Some(datavalue) = &snak.datavalue {
+                    *schema
+                        .value_types
+                        .entry(datavalue.value_type.to_string())
+                        .or_insert(0) += 1;
+                    schema.add_example(&id, &datavalue.value, self.max_examples);
+                }
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: Schema) {
+        for (property, other) in other.properties {
+            let schema = self.properties.entry(property).or_default();
+            schema.statements += other.statements;
+            add_counts(&mut schema.datatypes, other.datatypes);
+            add_counts(&mut schema.value_types, other.value_types);
+            add_counts(&mut schema.snaktypes, other.snaktypes);
+            for (id, example) in &other.candidates {
+                schema.add_example(id, example, self.max_examples);
+            }
+        }
+    }
+
+    /// Properties sorted by id.
+    pub fn catalogue(&self) -> BTreeMap<String, PropertySchema> {
+        let mut catalogue: BTreeMap<String, PropertySchema> =
+            self.properties.clone().into_iter().collect();
+        for schema in catalogue.values_mut() {
+            schema.examples = schema.candidates.iter().map(|(_, v)| v.clone()).collect();
+        }
+        catalogue
+    }
+}
+
+/// Parse every entity of the dump with all properties and infer the schema of each property.
+pub fn scan_schema(config: &Config, max_examples: usize) -> BTreeMap<String, PropertySchema> {
+    let (schema, _) = scan_entities(
+        config,
+        Some(vec![]),
+        None,
+        |_| true,
+        move || Schema::new(max_examples),
+        |entity, schema| schema.record(entity),
+        Schema::merge,
+    );
+    let catalogue = schema.catalogue();
+    info!("{} properties", catalogue.len());
+    catalogue
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::EntitySeed;
+    use crate::schema::*;
+    use serde_json::json;
+
+    const LINES: [&str; 3] = [
+        r#"{"type":"item","id":"Q1","claims":{"P213":[{"mainsnak":{"snaktype":"value","property":"P213","datavalue":{"value":"0000 0001 2146 438X","type":"string"},"datatype":"external-id"},"rank":"normal"}],"P1082":[{"mainsnak":{"snaktype":"somevalue","property":"P1082","datatype":"quantity"},"rank":"normal"}]}}"#,
+        r#"{"type":"item","id":"Q2","claims":{"P213":[{"mainsnak":{"snaktype":"value","property":"P213","datavalue":{"value":"0000 0001 2146 438X","type":"string"},"datatype":"external-id"},"rank":"normal"},{"mainsnak":{"snaktype":"value","property":"P213","datavalue":{"value":"0000 0004 0000 0001","type":"string"},"datatype":"external-id"},"rank":"normal"}]}}"#,
+        r#"{"type":"item","id":"Q3","claims":{"P1082":[{"mainsnak":{"snaktype":"value","property":"P1082","datavalue":{"value":{"amount":"+13960000","unit":"1"},"type":"quantity"},"datatype":"quantity"},"rank":"normal"}]}}"#,
+    ];
+
+    fn schema_of(lines: &[&str], max_examples: usize) -> Schema {
+        let mut schema = Schema::new(max_examples);
+        for line in lines {
+            let entity = Entity::parse(line.as_bytes(), EntitySeed::new(&[], None)).unwrap();
+            schema.record(&entity);
+        }
+        schema
+    }
+
+    #[test]
+    fn check_record() {
+        let catalogue = schema_of(&LINES, 1).catalogue();
+        let isni = &catalogue["P213"];
+        assert_eq!(isni.statements, 3);
+        assert_eq!(isni.datatypes["external-id"], 3);
+        assert_eq!(isni.value_types["string"], 3);
+        assert_eq!(isni.examples, vec![json!("0000 0001 2146 438X")]);
+        let population = &catalogue["P1082"];
+        assert_eq!(population.snaktypes["somevalue"], 1);
+        assert_eq!(population.value_types["quantity"], 1);
+        assert_eq!(population.datatypes["quantity"], 2);
+    }
+
+    #[test]
+    fn check_merge() {
+        let mut schema = schema_of(&LINES[..1], 2);
+        schema.merge(schema_of(&LINES[1..], 2));
+        assert_eq!(schema.catalogue(), schema_of(&LINES, 2).catalogue());
+        let isni = &schema.catalogue()["P213"];
+        assert_eq!(isni.examples.len(), 2);
+    }
+
+    #[test]
+    fn check_examples_order() {
+        // the chunks merge in any order, the examples come from the lowest ids
+        let mut reversed = schema_of(&LINES[2..], 1);
+        reversed.merge(schema_of(&LINES[1..2], 1));
+        reversed.merge(schema_of(&LINES[..1], 1));
+        let mut forward = schema_of(&LINES[..1], 1);
+        forward.merge(schema_of(&LINES[1..], 1));
+        assert_eq!(reversed.catalogue(), forward.catalogue());
+        assert_eq!(
+            reversed.catalogue()["P213"].examples,
+            vec![json!("0000 0001 2146 438X")]
+        );
+
+        // Q9 sorts before Q10
+        let q9 = LINES[0]
+            .replace("\"Q1\"", "\"Q9\"")
+            .replace("2146 438X", "0000 0001");
+        let q10 = LINES[0].replace("\"Q1\"", "\"Q10\"");
+        let mut schema = schema_of(&[&q10], 1);
+        schema.merge(schema_of(&[&q9], 1));
+        assert_eq!(
+            schema.catalogue()["P213"].examples,
+            vec![json!("0000 0001 0000 0001")]
+        );
+        assert_eq!(schema.catalogue(), schema_of(&[&q9, &q10], 1).catalogue());
+    }
+}
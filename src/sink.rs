@@ -0,0 +1,140 @@
+use crate::parser::Document;
+
+/// Destination of the filtered documents.
+///
+/// `parse_and_output_with` reads the dump on the calling thread and opens one shard per chunk of
+/// lines. Each shard is moved to a worker thread, gets the documents of its chunk and is flushed
+/// when the chunk is done. `finish` is called once after all shards are flushed.
+pub trait Sink {
+    type Shard: SinkShard;
+
+    fn open_shard(&mut self) -> Self::Shard;
+
+    fn finish(&mut self) {}
+
+    /// Files written by the sink, reported in the statistics of the run.
+    fn output_files(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+/// One shard of a `Sink`, written by a single worker thread.
+pub trait SinkShard: Send + 'static {
+    fn write(&mut self, doc: Document);
+
+    fn flush(&mut self);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_and_output_with, Config, Document};
+    use crate::sink::*;
+    use crate::test_util::temp_path;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    struct VecSink {
+        docs: Arc<Mutex<Vec<String>>>,
+        shards: usize,
+        finished: bool,
+    }
+
+    struct VecShard {
+        docs: Arc<Mutex<Vec<String>>>,
+        buffer: Vec<String>,
+    }
+
+    impl Sink for VecSink {
+        type Shard = VecShard;
+
+        fn open_shard(&mut self) -> VecShard {
+            self.shards += 1;
+            VecShard {
+                docs: self.docs.clone(),
+                buffer: vec![],
+            }
+        }
+
+        fn finish(&mut self) {
+            self.finished = true;
+        }
+    }
+
+    impl SinkShard for VecShard {
+        fn write(&mut self, doc: Document) {
+            self.buffer.push(doc.id().unwrap().to_string());
+        }
+
+        fn flush(&mut self) {
+            self.docs.lock().unwrap().append(&mut self.buffer);
+        }
+    }
+
+    #[test]
+    fn check_custom_sink() {
+        let path = temp_path("check_custom_sink.json.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        writeln!(encoder, "[").unwrap();
+        for i in 1..=5 {
+            writeln!(
+                encoder,
+                r#"{{"type":"item","id":"Q{}","labels":{{"ja":{{"language":"ja","value":"v"}}}}}},"#,
+                i
+            )
+            .unwrap();
+        }
+        writeln!(encoder, "]").unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+
+        let config = Config::builder()
+            .input_file(path.to_str().unwrap())
+            .chunk_size(2)
+            .build();
+        let mut sink = VecSink {
+            docs: Arc::new(Mutex::new(vec![])),
+            shards: 0,
+            finished: false,
+        };
+        parse_and_output_with(&config, &mut sink);
+        std::fs::remove_file(&path).unwrap();
+
+        let mut docs = sink.docs.lock().unwrap().clone();
+        docs.sort();
+        assert_eq!(docs, vec!["Q1", "Q2", "Q3", "Q4", "Q5"]);
+        assert_eq!(sink.shards, 3);
+        assert!(sink.finished);
+    }
+
+    #[test]
+    fn check_sample_one_shard() {
+        let path = temp_path("check_sample_one_shard.json");
+        let mut json = String::from("[\n");
+        for i in 1..=5 {
+            json.push_str(&format!(
+                "{{\"type\":\"item\",\"id\":\"Q{}\",\"labels\":{{\"ja\":{{\"language\":\"ja\",\"value\":\"v\"}}}}}},\n",
+                i
+            ));
+        }
+        json.push_str("]\n");
+        std::fs::write(&path, json).unwrap();
+
+        let config = Config::builder()
+            .input_file(path.to_str().unwrap())
+            .chunk_size(2)
+            .sample_size(2)
+            .build();
+        let mut sink = VecSink {
+            docs: Arc::new(Mutex::new(vec![])),
+            shards: 0,
+            finished: false,
+        };
+        let report = parse_and_output_with(&config, &mut sink);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sink.docs.lock().unwrap().len(), 2);
+        assert_eq!(report.entities_emitted, 2);
+        assert_eq!(sink.shards, 1);
+    }
+}
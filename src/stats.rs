@@ -0,0 +1,257 @@
+use crate::parser::Document;
+use serde_derive::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Counters of one chunk, collected by a worker without synchronization.
+#[derive(Debug, Default)]
+pub struct ChunkStats {
+    pub entities_parsed: u64,
+    pub entities_emitted: u64,
+    pub with_labels: u64,
+    pub with_descriptions: u64,
+    pub with_aliases: u64,
+    pub errors: u64,
+    /// Property id to the number of values written out.
+    pub property_values: HashMap<String, u64>,
+}
+
+impl ChunkStats {
+    pub(crate) fn record_emitted(&mut self, doc: &Document) {
+        let fields = doc.fields();
+        self.entities_emitted += 1;
+        if fields.contains_key("labels") {
+            self.with_labels += 1;
+        }
+        if fields.contains_key("descriptions") {
+            self.with_descriptions += 1;
+        }
+        if fields.contains_key("aliases") {
+            self.with_aliases += 1;
+        }
+        if let Some(Value::Object(claims)) = fields.get("claims") {
+            for (property, values) in claims {
+                let count = values.as_array().map(|x| x.len()).unwrap_or(1) as u64;
+                *self.property_values.entry(property.clone()).or_insert(0) += count;
+            }
+        }
+    }
+}
+
+/// Counters of the whole run, shared by the reader thread and the workers.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    pub(crate) lines_read: AtomicU64,
+    pub(crate) lines_skipped: AtomicU64,
+    pub(crate) entities_parsed: AtomicU64,
+    pub(crate) entities_emitted: AtomicU64,
+    with_labels: AtomicU64,
+    with_descriptions: AtomicU64,
+    with_aliases: AtomicU64,
+    pub(crate) errors: AtomicU64,
+    /// Entities allowed to be written out by `max_emitted`, reserved before writing.
+    emitted_reserved: AtomicU64,
+    property_values: Mutex<BTreeMap<String, u64>>,
+    output_files: Mutex<Vec<String>>,
+}
+
+/// Summary of a run, written by `--stats-file`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StatsReport {
+    pub lines_read: u64,
+    pub lines_skipped: u64,
+    pub entities_parsed: u64,
+    pub entities_emitted: u64,
+    pub entities_with_labels: u64,
+    pub entities_with_descriptions: u64,
+    pub entities_with_aliases: u64,
+    pub property_values: BTreeMap<String, u64>,
+    pub errors: u64,
+    pub output_files: Vec<String>,
+}
+
+impl RunStats {
+    pub(crate) fn add_chunk(&self, chunk: ChunkStats) {
+        self.entities_parsed
+            .fetch_add(chunk.entities_parsed, Ordering::Relaxed);
+        self.entities_emitted
+            .fetch_add(chunk.entities_emitted, Ordering::Relaxed);
+        self.with_labels
+            .fetch_add(chunk.with_labels, Ordering::Relaxed);
+        self.with_descriptions
+            .fetch_add(chunk.with_descriptions, Ordering::Relaxed);
+        self.with_aliases
+            .fetch_add(chunk.with_aliases, Ordering::Relaxed);
+        self.errors.fetch_add(chunk.errors, Ordering::Relaxed);
+        let mut property_values = self.property_values.lock().unwrap();
+        for (property, count) in chunk.property_values {
+            *property_values.entry(property).or_insert(0) += count;
+        }
+    }
+
+    /// Reserve one entity to write out. False if `max_emitted` (0 = no limit) is already reached.
+    pub(crate) fn reserve_emitted(&self, max_emitted: u64) -> bool {
+        if max_emitted == 0 {
+            return true;
+        }
+        self.emitted_reserved
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |reserved| {
+                if reserved < max_emitted {
+                    Some(reserved + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+
+    pub(crate) fn emitted_limit_reached(&self, max_emitted: u64) -> bool {
+        max_emitted > 0 && self.emitted_reserved.load(Ordering::Relaxed) >= max_emitted
+    }
+
+    pub(crate) fn add_output_files(&self, files: Vec<String>) {
+        self.output_files.lock().unwrap().extend(files);
+    }
+
+    /// Counters in the Prometheus text exposition format. `bytes_read` is the input read so far.
+    pub fn prometheus(&self, bytes_read: u64) -> String {
+        let counters = [
+            ("lines_read", "Lines read from the input.", &self.lines_read),
+            (
+                "lines_skipped",
+                "Lines skipped by the prefilter.",
+                &self.lines_skipped,
+            ),
+            ("entities_parsed", "Entities parsed.", &self.entities_parsed),
+            (
+                "entities_emitted",
+                "Entities written out.",
+                &self.entities_emitted,
+            ),
+            (
+                "errors",
+                "Lines that could not be read or parsed.",
+                &self.errors,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, help, counter) in counters.iter() {
+            write_counter(&mut text, name, help, counter.load(Ordering::Relaxed));
+        }
+        write_counter(
+            &mut text,
+            "input_bytes_read",
+            "Bytes read from the input file, compressed.",
+            bytes_read,
+        );
+        text
+    }
+
+    /// Rewrite a node-exporter textfile. Written to a temporary file and renamed,
+    /// so the collector never sees a partial file.
+    pub fn write_prometheus(&self, path: &str, bytes_read: u64) {
+        let tmp_path = format!("{}.tmp", path);
+        fs::write(&tmp_path, self.prometheus(bytes_read))
+            .unwrap_or_else(|_| panic!("can't write metrics file[{}]", tmp_path));
+        fs::rename(&tmp_path, path)
+            .unwrap_or_else(|_| panic!("can't rename metrics file[{}]", path));
+    }
+
+    pub fn report(&self) -> StatsReport {
+        StatsReport {
+            lines_read: self.lines_read.load(Ordering::Relaxed),
+            lines_skipped: self.lines_skipped.load(Ordering::Relaxed),
+            entities_parsed: self.entities_parsed.load(Ordering::Relaxed),
+            entities_emitted: self.entities_emitted.load(Ordering::Relaxed),
+            entities_with_labels: self.with_labels.load(Ordering::Relaxed),
+            entities_with_descriptions: self.with_descriptions.load(Ordering::Relaxed),
+            entities_with_aliases: self.with_aliases.load(Ordering::Relaxed),
+            property_values: self.property_values.lock().unwrap().clone(),
+            errors: self.errors.load(Ordering::Relaxed),
+            output_files: self.output_files.lock().unwrap().clone(),
+        }
+    }
+}
+
+fn write_counter(text: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(text, "# HELP wikidata_filter_{}_total {}", name, help);
+    let _ = writeln!(text, "# TYPE wikidata_filter_{}_total counter", name);
+    let _ = writeln!(text, "wikidata_filter_{}_total {}", name, value);
+}
+
+impl StatsReport {
+    pub fn write(&self, path: &str) {
+        let file =
+            File::create(path).unwrap_or_else(|_| panic!("can't create stats file[{}]", path));
+        serde_json::to_writer_pretty(file, self).expect("Error on writing stats");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::Config;
+    use crate::stats::*;
+    use crate::stream::documents;
+
+    #[test]
+    fn check_stats() {
+        let dump = r#"[
+{"type":"item","id":"Q1","labels":{"ja":{"language":"ja","value":"宇宙"}},"descriptions":{"ja":{"language":"ja","value":"すべて"}},"claims":{"P31":[{"mainsnak":{"datavalue":{"value":{"id":"Q36906466"},"type":"wikibase-entityid"}}},{"mainsnak":{"datavalue":{"value":{"id":"Q1454986"},"type":"wikibase-entityid"}}}]}},
+{"type":"item","id":"Q2","labels":{"ja":{"language":"ja","value":"地球"}},"aliases":{"ja":[{"language":"ja","value":"ちきゅう"}]}},
+{"type":"item","id":"Q3","labels":{"ja":{"language":"ja","value":"生命"}},"claims":{"P31":[{"mainsnak":{"datavalue":{"value":{"id":"Q1"},"type":"wikibase-entityid"}}}]}},
+{"type":"item","id":"Q4","labels":{"ja": broken
+]"#;
+        let config = Config::builder().properties(&["P31"]).build();
+        let mut docs = documents(dump.as_bytes(), &config);
+        let mut chunk = ChunkStats::default();
+        for doc in docs.by_ref() {
+            chunk.record_emitted(&doc);
+        }
+        assert_eq!(docs.stats().entities_parsed, 3);
+        assert_eq!(docs.stats().errors, 1);
+
+        let stats = RunStats::default();
+        stats.add_chunk(chunk);
+        stats.add_output_files(vec![String::from("out_0.json")]);
+        let report = stats.report();
+        assert_eq!(report.entities_emitted, 3);
+        assert_eq!(report.entities_with_labels, 3);
+        assert_eq!(report.entities_with_descriptions, 1);
+        assert_eq!(report.entities_with_aliases, 1);
+        assert_eq!(report.property_values.get("P31"), Some(&3));
+        assert_eq!(report.output_files, vec!["out_0.json"]);
+    }
+
+    #[test]
+    fn check_prometheus() {
+        let stats = RunStats::default();
+        stats.lines_read.fetch_add(10, Ordering::Relaxed);
+        stats.add_chunk(ChunkStats {
+            entities_emitted: 4,
+            errors: 1,
+            ..ChunkStats::default()
+        });
+        let text = stats.prometheus(2048);
+        assert!(text.contains("# TYPE wikidata_filter_lines_read_total counter\n"));
+        assert!(text.contains("\nwikidata_filter_lines_read_total 10\n"));
+        assert!(text.contains("\nwikidata_filter_entities_emitted_total 4\n"));
+        assert!(text.contains("\nwikidata_filter_errors_total 1\n"));
+        assert!(text.contains("\nwikidata_filter_input_bytes_read_total 2048\n"));
+    }
+
+    #[test]
+    fn check_reserve_emitted() {
+        let stats = RunStats::default();
+        assert!(!stats.emitted_limit_reached(2));
+        assert!(stats.reserve_emitted(2));
+        assert!(stats.reserve_emitted(2));
+        assert!(!stats.reserve_emitted(2));
+        assert!(stats.emitted_limit_reached(2));
+        assert!(stats.reserve_emitted(0));
+        assert!(!stats.emitted_limit_reached(0));
+    }
+}
@@ -0,0 +1,169 @@
+use crate::decompress::open_input;
+use crate::parser::{default_threads, is_entity_line, parse_article, skip_parse, Config, Document};
+use crate::stats::ChunkStats;
+use log::warn;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines, Read};
+
+/// Iterator over the filtered documents of a Wikidata JSON dump.
+/// Documents are parsed on the calling thread, in the order of the dump.
+pub struct DocumentIter<R: BufRead> {
+    lines: Lines<R>,
+    config: Config,
+    count: u64,
+    entity_lines: u64,
+    stats: ChunkStats,
+}
+
+impl<R: BufRead> DocumentIter<R> {
+    /// Counters of the documents read so far.
+    pub fn stats(&self) -> &ChunkStats {
+        &self.stats
+    }
+}
+
+impl<R: BufRead> Iterator for DocumentIter<R> {
+    type Item = Document;
+
+    fn next(&mut self) -> Option<Document> {
+        let config = &self.config;
+        loop {
+            if config.max_lines > 0 && self.entity_lines >= config.skip + config.max_lines {
+                return None;
+            }
+            if config.max_emitted > 0 && self.stats.entities_emitted >= config.max_emitted {
+                return None;
+            }
+            let line = self.lines.next()?;
+            self.count += 1;
+            match line {
+                Ok(article) => {
+                    if !is_entity_line(&article) {
+                        continue;
+                    }
+                    self.entity_lines += 1;
+                    if self.entity_lines > config.skip && !skip_parse(&article, config) {
+                        if let Some(doc) = parse_article(article, config, &mut self.stats) {
+                            self.stats.record_emitted(&doc);
+                            return Some(doc);
+                        }
+                    }
+                }
+                Err(_) => {
+                    warn!("Read line error. line[{}]", self.count);
+                    self.stats.errors += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Filter a decompressed dump, e.g. `File` of `latest-all.json` or the result of `open_dump`.
+///
+/// ```no_run
+/// use wikidata_filter::parser::Config;
+/// use wikidata_filter::stream::{documents, open_dump};
+///
+/// let config = Config::builder().language("en").properties(&["P31"]).build();
+/// for doc in documents(open_dump("latest-all.json.gz"), &config) {
+///     println!("{}", doc.to_json_string());
+/// }
+/// ```
+pub fn documents<R: Read>(reader: R, config: &Config) -> DocumentIter<BufReader<R>> {
+    DocumentIter {
+        lines: BufReader::new(reader).lines(),
+        config: config.clone(),
+        count: 0,
+        entity_lines: 0,
+        stats: ChunkStats::default(),
+    }
+}
+
+/// Callback version of `documents`.
+pub fn for_each_document<R: Read, F: FnMut(Document)>(reader: R, config: &Config, f: F) {
+    documents(reader, config).for_each(f);
+}
+
+/// Open a Wikidata dump file, `.json.gz`, `.json.bz2` or uncompressed `.json`.
+/// bgzip, multi-member gzip and multistream bzip2 files are decompressed in parallel.
+pub fn open_dump(path: &str) -> Box<dyn Read + Send> {
+    let file = File::open(path).expect("Input file open error");
+    open_input(file, default_threads())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::idset::IdSet;
+    use crate::parser::Config;
+    use crate::stream::*;
+
+    const DUMP: &str = r#"[
+{"type":"item","id":"Q1","labels":{"ja":{"language":"ja","value":"宇宙"},"en":{"language":"en","value":"universe"}},"descriptions":{},"aliases":{},"claims":{"P31":[{"mainsnak":{"snaktype":"value","property":"P31","datavalue":{"value":{"entity-type":"item","numeric-id":36906466,"id":"Q36906466"},"type":"wikibase-entityid"},"datatype":"wikibase-item"},"type":"statement","rank":"normal"}]}},
+{"type":"item","id":"Q2","labels":{"en":{"language":"en","value":"Earth"}},"descriptions":{},"aliases":{},"claims":{}},
+{"type":"item","id":"Q3","labels":{"ja":{"language":"ja","value":"生命"}},"descriptions":{},"aliases":{},"claims":{}}
+]"#;
+
+    #[test]
+    fn check_documents() {
+        let config = Config::builder().properties(&["p31"]).build();
+        let docs: Vec<Document> = documents(DUMP.as_bytes(), &config).collect();
+        let ids: Vec<&str> = docs.iter().filter_map(|doc| doc.id()).collect();
+        assert_eq!(ids, vec!["Q1", "Q3"]);
+        assert_eq!(
+            docs[0].to_json_string(),
+            r#"{"claims":{"P31":["Q36906466"]},"id":"Q1","labels":"宇宙"}"#
+        );
+
+        let mut count = 0;
+        let config = Config::builder().language("en").limit(2).build();
+        for_each_document(DUMP.as_bytes(), &config, |_| count += 1);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn check_limits() {
+        let ids = |config: &Config| -> Vec<String> {
+            documents(DUMP.as_bytes(), config)
+                .filter_map(|doc| doc.id().map(String::from))
+                .collect()
+        };
+        let config = Config::builder().max_lines(1).build();
+        assert_eq!(ids(&config), vec!["Q1"]);
+        let config = Config::builder().skip(1).build();
+        assert_eq!(ids(&config), vec!["Q3"]);
+        let config = Config::builder().skip(1).max_lines(1).build();
+        assert!(ids(&config).is_empty());
+        let config = Config::builder().language("en").max_emitted(1).build();
+        assert_eq!(ids(&config), vec!["Q1"]);
+        let config = Config::builder()
+            .language("en")
+            .skip(1)
+            .max_emitted(5)
+            .build();
+        assert_eq!(ids(&config), vec!["Q2"]);
+    }
+
+    #[test]
+    fn check_id_lists() {
+        let ids = |config: &Config| -> Vec<String> {
+            documents(DUMP.as_bytes(), config)
+                .filter_map(|doc| doc.id().map(String::from))
+                .collect()
+        };
+        let config = Config::builder()
+            .include_ids(IdSet::new(&["Q3", "Q2"]))
+            .build();
+        assert_eq!(ids(&config), vec!["Q3"]);
+        let config = Config::builder().exclude_ids(IdSet::new(&["Q3"])).build();
+        assert_eq!(ids(&config), vec!["Q1"]);
+    }
+
+    #[test]
+    fn check_sample_rate() {
+        let count = |config: &Config| documents(DUMP.as_bytes(), config).count();
+        assert_eq!(count(&Config::builder().sample_rate(1.0).build()), 2);
+        assert_eq!(count(&Config::builder().sample_rate(0.0).build()), 0);
+        let config = Config::builder().sample_rate(0.5).sample_seed(1).build();
+        assert_eq!(count(&config), count(&config));
+    }
+}
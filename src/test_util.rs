@@ -0,0 +1,16 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Path in the temp directory that is unique to this process and call, so tests
+/// running in parallel or in concurrent `cargo test` runs never share a file.
+pub fn temp_path(name: &str) -> PathBuf {
+    let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+    std::env::temp_dir().join(format!(
+        "wikidata_filter_{}_{}_{}",
+        std::process::id(),
+        n,
+        name
+    ))
+}
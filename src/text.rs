@@ -0,0 +1,140 @@
+use crate::entity::Entity;
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextField {
+    Labels,
+    Descriptions,
+    Aliases,
+}
+
+#[derive(Debug, Clone)]
+pub enum TextMatcher {
+    Regex(Regex),
+    Prefix(String),
+    /// A character of a Unicode script, e.g. `Han`, as the regex `\p{Han}`.
+    Script(Regex),
+}
+
+impl TextMatcher {
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            TextMatcher::Regex(regex) | TextMatcher::Script(regex) => regex.is_match(text),
+            TextMatcher::Prefix(prefix) => text.starts_with(prefix.as_str()),
+        }
+    }
+}
+
+/// A predicate on the label, description or aliases in the output language.
+#[derive(Debug, Clone)]
+pub struct TextFilter {
+    field: TextField,
+    matcher: TextMatcher,
+}
+
+impl TextFilter {
+    pub fn new(field: TextField, matcher: TextMatcher) -> Self {
+        TextFilter { field, matcher }
+    }
+
+    /// `FIELD:KIND:PATTERN` with FIELD `labels`, `descriptions` or `aliases` and KIND `regex`,
+    /// `prefix` or `script`, e.g. `labels:script:Han` or `descriptions:regex:^日本の`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.splitn(3, ':');
+        let field = match parts.next() {
+            Some("labels") => TextField::Labels,
+            Some("descriptions") => TextField::Descriptions,
+            Some("aliases") => TextField::Aliases,
+            _ => {
+                return Err(format!(
+                    "{}: labels, descriptions or aliases expected",
+                    spec
+                ))
+            }
+        };
+        let kind = parts.next();
+        let pattern = parts
+            .next()
+            .ok_or_else(|| format!("{}: FIELD:KIND:PATTERN expected", spec))?;
+        let matcher = match kind {
+            Some("regex") => TextMatcher::Regex(Regex::new(pattern).map_err(|e| e.to_string())?),
+            Some("prefix") => TextMatcher::Prefix(pattern.to_string()),
+            Some("script") => {
+                let unknown = || format!("{}: unknown Unicode script {}", spec, pattern);
+                if pattern.is_empty()
+                    || !pattern
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_')
+                {
+                    return Err(unknown());
+                }
+                TextMatcher::Script(
+                    Regex::new(&format!(r"\p{{{}}}", pattern)).map_err(|_| unknown())?,
+                )
+            }
+            _ => return Err(format!("{}: regex, prefix or script expected", spec)),
+        };
+        Ok(TextFilter { field, matcher })
+    }
+
+    /// True if the text in `lang` matches. For aliases, any alias.
+    pub fn matches(&self, entity: &Entity, lang: &str) -> bool {
+        match self.field {
+            TextField::Labels => entity
+                .labels
+                .get(lang)
+                .is_some_and(|x| self.matcher.is_match(x)),
+            TextField::Descriptions => entity
+                .descriptions
+                .get(lang)
+                .is_some_and(|x| self.matcher.is_match(x)),
+            TextField::Aliases => entity
+                .aliases
+                .get(lang)
+                .is_some_and(|aliases| aliases.iter().any(|x| self.matcher.is_match(x))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entity::Entity;
+    use crate::text::*;
+
+    fn entity<'a>(label: &'a str, description: &'a str, aliases: Vec<&'a str>) -> Entity<'a> {
+        let mut entity = Entity::default();
+        entity.labels.insert("ja".into(), label.into());
+        entity.descriptions.insert("ja".into(), description.into());
+        entity
+            .aliases
+            .insert("ja".into(), aliases.into_iter().map(|x| x.into()).collect());
+        entity
+    }
+
+    #[test]
+    fn check_text_filter() {
+        let tokyo = entity("東京都", "日本の首都", vec!["とうきょう", "TYO"]);
+        let talisker = entity("タリスカー", "スコットランドの蒸留所", vec![]);
+
+        let kanji = TextFilter::parse("labels:script:Han").unwrap();
+        assert!(kanji.matches(&tokyo, "ja"));
+        assert!(!kanji.matches(&talisker, "ja"));
+        assert!(!kanji.matches(&tokyo, "en"));
+
+        let japan = TextFilter::parse("descriptions:regex:^日本の").unwrap();
+        assert!(japan.matches(&tokyo, "ja"));
+        assert!(!japan.matches(&talisker, "ja"));
+
+        let prefix = TextFilter::parse("aliases:prefix:TY").unwrap();
+        assert!(prefix.matches(&tokyo, "ja"));
+        assert!(!prefix.matches(&talisker, "ja"));
+
+        assert!(TextFilter::parse("labels:regex:a:b").is_ok());
+        assert!(TextFilter::parse("labels:script:Klingon").is_err());
+        assert!(TextFilter::parse("labels:script:Han}|.").is_err());
+        assert!(TextFilter::parse("labels:script:").is_err());
+        assert!(TextFilter::parse("labels:regex:(").is_err());
+        assert!(TextFilter::parse("sitelinks:prefix:a").is_err());
+        assert!(TextFilter::parse("labels:prefix").is_err());
+    }
+}